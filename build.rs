@@ -0,0 +1,26 @@
+use std::{env, fs, path::Path};
+
+// Embeds every png in src/textures, the block texture array is built from them at startup.
+// Writes a list of (name, bytes) pairs, name being the file name without .png.
+fn main() {
+    let dir = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("src/textures");
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let mut textures: Vec<(String, String)> = fs::read_dir(&dir)
+        .expect("missing src/textures")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "png"))
+        .map(|path| (
+            path.file_stem().unwrap().to_string_lossy().into_owned(),
+            path.display().to_string(),
+        ))
+        .collect();
+    textures.sort();
+
+    let mut out = String::from("&[\n");
+    for (name, path) in textures {
+        out += &format!("    ({:?}, include_bytes!({:?})),\n", name, path);
+    }
+    out += "]\n";
+    fs::write(Path::new(&env::var("OUT_DIR").unwrap()).join("textures.rs"), out).unwrap();
+}
@@ -0,0 +1,96 @@
+use crate::block::BlockType;
+
+// Biomes are picked per column from the climate, low frequency noise fields in -1 to 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Forest,
+    Tundra,
+    Mountains,
+}
+
+pub const BIOMES: [Biome; 5] = [Biome::Plains, Biome::Desert, Biome::Forest, Biome::Tundra, Biome::Mountains];
+
+impl Biome {
+    // ruggedness raises mountains anywhere, otherwise it's warmth and rain that decide
+    pub fn from_climate(temperature: f64, humidity: f64, ruggedness: f64) -> Self {
+        if ruggedness > 0.45 {
+            Biome::Mountains
+        } else if temperature < -0.25 {
+            Biome::Tundra
+        } else if temperature > 0.2 && humidity < 0.1 {
+            Biome::Desert
+        } else if humidity > 0.1 {
+            Biome::Forest
+        } else {
+            Biome::Plains
+        }
+    }
+
+    pub fn properties(self) -> BiomeProperties {
+        // a world's blocks.ron may have only the built-in blocks, biomes do without the others
+        let plants = |plants: &[(&str, f64, u32)]| plants.iter()
+            .filter_map(|&(name, chance, max_height)| Some((BlockType::from_name(name)?, chance, max_height)))
+            .collect();
+        match self {
+            Biome::Plains => BiomeProperties {
+                top: BlockType::GRASS,
+                filler: BlockType::DIRT,
+                height_offset: 0.0,
+                height_scale: 0.5,
+                freezes: false,
+                trees: 0.003,
+                plants: plants(&[("tall_grass", 0.12, 1), ("flower", 0.015, 1)]),
+            },
+            Biome::Desert => BiomeProperties {
+                top: BlockType::SAND,
+                filler: BlockType::SAND,
+                height_offset: 2.0,
+                height_scale: 0.4,
+                freezes: false,
+                trees: 0.0,
+                plants: plants(&[("cactus", 0.006, 3)]),
+            },
+            Biome::Forest => BiomeProperties {
+                top: BlockType::GRASS,
+                filler: BlockType::DIRT,
+                height_offset: 3.0,
+                height_scale: 1.0,
+                freezes: false,
+                trees: 0.03,
+                plants: plants(&[("tall_grass", 0.05, 1)]),
+            },
+            Biome::Tundra => BiomeProperties {
+                top: BlockType::from_name("snow").unwrap_or(BlockType::GRASS),
+                filler: BlockType::DIRT,
+                height_offset: 0.0,
+                height_scale: 0.7,
+                freezes: true,
+                trees: 0.002,
+                plants: plants(&[]),
+            },
+            Biome::Mountains => BiomeProperties {
+                top: BlockType::GRASS,
+                filler: BlockType::DIRT,
+                height_offset: 30.0,
+                height_scale: 2.5,
+                freezes: false,
+                trees: 0.004,
+                plants: plants(&[("tall_grass", 0.03, 1)]),
+            },
+        }
+    }
+}
+
+pub struct BiomeProperties {
+    pub top: BlockType, // surface block above the beaches
+    pub filler: BlockType, // the SURFACE_DEPTH blocks below it
+    // added to the continental height, and how much the hills on top of it are scaled
+    pub height_offset: f64,
+    pub height_scale: f64,
+    pub freezes: bool, // water at sea level turns to ice
+    // chance of a tree per surface block, then of each plant, which stacks up to its max height
+    pub trees: f64,
+    pub plants: Vec<(BlockType, f64, u32)>,
+}
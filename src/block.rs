@@ -1,57 +1,372 @@
 use crate::mesh::MeshVertex;
-use std::slice::Iter;
+use crate::block_textures::{BlockTextures, MISSING_TEXTURE, block_textures};
+use crate::model::{BlockModel, ModelBox};
+use crate::block_entity::BlockEntityKind;
+use nalgebra::{Vector3, vector};
+use serde::Deserialize;
+use anyhow::{Result, bail};
+use std::{
+    collections::HashMap,
+    fmt,
+    slice::Iter,
+    sync::OnceLock,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum BlockType {
-    Air,
-    Grass,
-    Dirt,
-    Stone,
-    Sand,
-    Wood,
-    Leaves,
-    Water,
-}
+// Id of a block in the registry, which is what chunks store. The blocks the engine refers to by
+// name have fixed ids, blocks.ron has to list them first and in this order.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockType(pub u16);
 
 impl BlockType {
-    pub fn texture(&self, face: &BlockFace) -> u32 {
-        match *self {
-            BlockType::Grass => match face {
-                BlockFace::Top => 0,
-                BlockFace::Bottom => 2,
-                _ => 1,
-            },
-            BlockType::Dirt => 2,
-            BlockType::Stone => 3,
-            BlockType::Sand => 4,
-            BlockType::Wood => match face {
-                BlockFace::Top => 22,
-                BlockFace::Bottom => 22,
-                _ => 6,
-            },
-            BlockType::Leaves => 7,
-            BlockType::Water => 8,
-            _ => 255, // Missing Texture
-        }
+    pub const AIR: Self = Self(0);
+    pub const GRASS: Self = Self(1);
+    pub const DIRT: Self = Self(2);
+    pub const STONE: Self = Self(3);
+    pub const SAND: Self = Self(4);
+    pub const WOOD: Self = Self(5);
+    pub const LEAVES: Self = Self(6);
+    pub const WATER: Self = Self(7);
+
+    const BUILTIN: &'static [&'static str] = &[
+        "air", "grass", "dirt", "stone", "sand", "wood", "leaves", "water",
+    ];
+
+    pub fn properties(&self) -> &'static BlockProperties {
+        registry().get(*self)
+    }
+
+    // stable names used when saving, so blocks can be added or reordered freely
+    pub fn name(&self) -> &'static str {
+        &self.properties().name
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        registry().by_name(name)
     }
 
     pub fn opaque(&self) -> bool {
-        match *self {
-            BlockType::Air => false,
-            BlockType::Water => false,
-            _ => true,
+        self.properties().opaque
+    }
+
+    // whether the orientation in the block's state changes how it looks
+    pub fn oriented(&self) -> bool {
+        self.properties().oriented
+    }
+}
+
+impl fmt::Debug for BlockType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match registry().blocks.get(self.0 as usize) {
+            Some(properties) => write!(f, "BlockType({})", properties.name),
+            None => write!(f, "BlockType(#{})", self.0),
         }
     }
+}
 
-    pub fn transparent(&self) -> bool {
-        match *self {
-            BlockType::Water => true,
-            _ => false,
+// An entry in blocks.ron. Textures are named after their file in src/textures. Faces without a
+// texture use side (front, back, left and right only), then all, then the missing texture.
+#[derive(Deserialize)]
+struct BlockDef {
+    name: String,
+    #[serde(default)]
+    textures: TextureDef,
+    #[serde(default = "default_true")]
+    opaque: bool,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default = "default_true")]
+    collision: bool,
+    #[serde(default)]
+    light: u8,
+    #[serde(default)]
+    oriented: bool,
+    #[serde(default)]
+    fluid: bool,
+    #[serde(default)]
+    falls: bool,
+    #[serde(default)]
+    entity: Option<BlockEntityKind>,
+    #[serde(default = "default_color")]
+    color: (u8, u8, u8),
+    #[serde(default)]
+    model: ModelDef,
+}
+
+// Boxes are given as (from: (x, y, z), to: (x, y, z)) in sixteenths of a block
+#[derive(Deserialize, Default)]
+enum ModelDef {
+    #[default]
+    Cube,
+    Boxes(Vec<BoxDef>),
+    Cross,
+    Fence,
+}
+
+#[derive(Deserialize)]
+struct BoxDef {
+    from: (u8, u8, u8),
+    to: (u8, u8, u8),
+}
+
+#[derive(Deserialize, Default)]
+struct TextureDef {
+    all: Option<String>,
+    side: Option<String>,
+    front: Option<String>,
+    back: Option<String>,
+    top: Option<String>,
+    bottom: Option<String>,
+    left: Option<String>,
+    right: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_color() -> (u8, u8, u8) {
+    (255, 0, 255)
+}
+
+pub struct BlockProperties {
+    pub name: String,
+    pub textures: [u32; 6], // texture array layers, indexed by BlockFace
+    pub opaque: bool,
+    pub transparent: bool, // drawn in the transparent pass
+    pub collision: bool, // solid to the player, blocks without it get replaced by placing
+    #[allow(dead_code)] // there is no lighting yet
+    pub light: u8, // 0-15
+    // oriented blocks are drawn with their top facing the orientation in their state, box models
+    // with their front, see model.rs
+    pub oriented: bool,
+    // fluids flow into free space next to and below them, the level in their state says how far
+    pub fluid: bool,
+    pub falls: bool, // turns into a falling block when there is nothing solid below it
+    pub entity: Option<BlockEntityKind>, // created when the block is placed
+    pub color: [u8; 3], // used where blocks are shown as a single color, like .vox export
+    pub model: BlockModel,
+}
+
+impl BlockProperties {
+    // whether the block has anything to draw
+    pub fn visible(&self) -> bool {
+        self.model != BlockModel::Cube || self.opaque || self.transparent
+    }
+
+    pub fn texture(&self, face: &BlockFace, state: BlockState) -> u32 {
+        if self.oriented {
+            self.textures[state.orientation().model_face(*face) as usize]
+        } else {
+            self.textures[*face as usize]
+        }
+    }
+}
+
+pub struct BlockRegistry {
+    blocks: Vec<BlockProperties>,
+    ids: HashMap<String, BlockType>,
+}
+
+impl BlockRegistry {
+    // unknown texture names only warn, the face gets the missing texture
+    pub fn from_ron(source: &str, textures: &BlockTextures) -> Result<Self> {
+        let defs: Vec<BlockDef> = ron::from_str(source)?;
+        if defs.len() > u16::MAX as usize {
+            bail!("too many blocks");
+        }
+
+        let mut blocks = Vec::with_capacity(defs.len());
+        let mut ids = HashMap::new();
+        for (i, def) in defs.into_iter().enumerate() {
+            if let Some(builtin) = BlockType::BUILTIN.get(i) {
+                if def.name != *builtin {
+                    bail!("block {} is {:?}, expected {:?}", i, def.name, builtin);
+                }
+            }
+            if ids.insert(def.name.clone(), BlockType(i as u16)).is_some() {
+                bail!("block {:?} is defined twice", def.name);
+            }
+            if def.light > 15 {
+                bail!("light of block {:?} is over 15", def.name);
+            }
+
+            if let ModelDef::Boxes(boxes) = &def.model {
+                if boxes.iter().any(|b| [b.from, b.to].iter().any(|&(x, y, z)| x.max(y).max(z) > 16)) {
+                    bail!("model of block {:?} goes outside the block", def.name);
+                }
+            }
+            let model = match def.model {
+                ModelDef::Cube => BlockModel::Cube,
+                ModelDef::Boxes(boxes) => BlockModel::Boxes(
+                    boxes.iter().map(|b| ModelBox::from_sixteenths(b.from, b.to)).collect()
+                ),
+                ModelDef::Cross => BlockModel::Cross,
+                ModelDef::Fence => BlockModel::Fence,
+            };
+
+            let t = &def.textures;
+            let all = t.all.as_ref();
+            let side = t.side.as_ref().or(all);
+            let mut layers = [0; 6];
+            for face in BlockFace::iterator() {
+                let name = match face {
+                    BlockFace::Front => t.front.as_ref().or(side),
+                    BlockFace::Back => t.back.as_ref().or(side),
+                    BlockFace::Left => t.left.as_ref().or(side),
+                    BlockFace::Right => t.right.as_ref().or(side),
+                    BlockFace::Top => t.top.as_ref().or(all),
+                    BlockFace::Bottom => t.bottom.as_ref().or(all),
+                };
+                let name = name.map_or(MISSING_TEXTURE, |name| name.as_str());
+                layers[*face as usize] = textures.id(name).unwrap_or_else(|| {
+                    eprintln!("block {:?} uses unknown texture {:?}", def.name, name);
+                    textures.id(MISSING_TEXTURE).unwrap()
+                });
+            }
+
+            blocks.push(BlockProperties {
+                name: def.name,
+                textures: layers,
+                // only full cubes hide what is behind them
+                opaque: def.opaque && model == BlockModel::Cube,
+                transparent: def.transparent,
+                collision: def.collision,
+                light: def.light,
+                oriented: def.oriented,
+                fluid: def.fluid,
+                falls: def.falls,
+                entity: def.entity,
+                color: [def.color.0, def.color.1, def.color.2],
+                model,
+            });
+        }
+        if blocks.len() < BlockType::BUILTIN.len() {
+            bail!("missing block {:?}", BlockType::BUILTIN[blocks.len()]);
+        }
+
+        Ok(Self {
+            blocks,
+            ids,
+        })
+    }
+
+    pub fn get(&self, block: BlockType) -> &BlockProperties {
+        &self.blocks[block.0 as usize]
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<BlockType> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BlockType, &BlockProperties)> {
+        self.blocks.iter().enumerate().map(|(i, properties)| (BlockType(i as u16), properties))
+    }
+}
+
+static REGISTRY: OnceLock<BlockRegistry> = OnceLock::new();
+
+// Uses a world's own block definitions, which can be edited without rebuilding, in place of the
+// built-in ones. Has to be called before any block is looked up; definitions that fail to load
+// only warn and leave the built-in blocks.
+pub fn load_registry(source: &str) {
+    match BlockRegistry::from_ron(source, block_textures()) {
+        Ok(registry) => if REGISTRY.set(registry).is_err() {
+            eprintln!("blocks were looked up before the world's blocks.ron was loaded, it is ignored");
+        },
+        Err(e) => eprintln!("invalid world/blocks.ron, using the built-in blocks: {:?}", e),
+    }
+}
+
+// the registry built from the built-in blocks.ron unless load_registry was called first. It is
+// shipped with the engine so failing to parse it is a bug.
+pub fn registry() -> &'static BlockRegistry {
+    REGISTRY.get_or_init(|| {
+        BlockRegistry::from_ron(include_str!("blocks.ron"), block_textures()).expect("invalid blocks.ron")
+    })
+}
+
+// Extra data stored alongside a block, packed into 16 bits:
+//   bits 0-2  orientation
+//   bits 3-5  level, e.g. how far water has flowed
+//   bits 6-8  growth stage
+//   bit 15    placed by the player
+// Blocks only use the fields that make sense for them, the rest stay 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct BlockState(pub u16);
+
+impl BlockState {
+    const ORIENTATION_SHIFT: u16 = 0;
+    const LEVEL_SHIFT: u16 = 3;
+    #[allow(dead_code)] // nothing grows yet
+    const GROWTH_SHIFT: u16 = 6;
+    const PLAYER_PLACED_BIT: u16 = 1 << 15;
+
+    fn field(&self, shift: u16) -> u8 {
+        ((self.0 >> shift) & 0b111) as u8
+    }
+
+    fn with_field(self, shift: u16, value: u8) -> Self {
+        Self((self.0 & !(0b111 << shift)) | (((value & 0b111) as u16) << shift))
+    }
+
+    // defaults to Top, i.e. pointing up
+    pub fn orientation(&self) -> BlockFace {
+        match self.field(Self::ORIENTATION_SHIFT) {
+            1 => BlockFace::Bottom,
+            2 => BlockFace::Front,
+            3 => BlockFace::Back,
+            4 => BlockFace::Left,
+            5 => BlockFace::Right,
+            _ => BlockFace::Top,
+        }
+    }
+
+    pub fn with_orientation(self, face: BlockFace) -> Self {
+        let value = match face {
+            BlockFace::Top => 0,
+            BlockFace::Bottom => 1,
+            BlockFace::Front => 2,
+            BlockFace::Back => 3,
+            BlockFace::Left => 4,
+            BlockFace::Right => 5,
+        };
+        self.with_field(Self::ORIENTATION_SHIFT, value)
+    }
+
+    // 0-7
+    pub fn level(&self) -> u8 {
+        self.field(Self::LEVEL_SHIFT)
+    }
+
+    pub fn with_level(self, level: u8) -> Self {
+        self.with_field(Self::LEVEL_SHIFT, level)
+    }
+
+    // 0-7
+    #[allow(dead_code)] // nothing grows yet
+    pub fn growth_stage(&self) -> u8 {
+        self.field(Self::GROWTH_SHIFT)
+    }
+
+    #[allow(dead_code)] // nothing grows yet
+    pub fn with_growth_stage(self, stage: u8) -> Self {
+        self.with_field(Self::GROWTH_SHIFT, stage)
+    }
+
+    pub fn player_placed(&self) -> bool {
+        self.0 & Self::PLAYER_PLACED_BIT != 0
+    }
+
+    pub fn with_player_placed(self, player_placed: bool) -> Self {
+        if player_placed {
+            Self(self.0 | Self::PLAYER_PLACED_BIT)
+        } else {
+            Self(self.0 & !Self::PLAYER_PLACED_BIT)
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BlockFace {
     Front,
     Back,
@@ -62,40 +377,40 @@ pub enum BlockFace {
 }
 
 const FRONT_FACE: &[MeshVertex] = &[
-    MeshVertex { position: [-0.5, 0.5, 0.5], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [0.5, 0.5, 0.5], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [-0.5, -0.5, 0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [0.5, -0.5, 0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
+    MeshVertex { position: [-0.5, 0.5, 0.5], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [0.5, 0.5, 0.5], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [-0.5, -0.5, 0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [0.5, -0.5, 0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
 ];
 const BACK_FACE: &[MeshVertex] = &[
-    MeshVertex { position: [0.5, 0.5, -0.5], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [-0.5, 0.5, -0.5], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [0.5, -0.5, -0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [-0.5, -0.5, -0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
+    MeshVertex { position: [0.5, 0.5, -0.5], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [-0.5, 0.5, -0.5], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [0.5, -0.5, -0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [-0.5, -0.5, -0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
 ];
 const TOP_FACE: &[MeshVertex] = &[
-    MeshVertex { position: [-0.5, 0.5, -0.5], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [0.5, 0.5, -0.5], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [-0.5, 0.5, 0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [0.5, 0.5, 0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
+    MeshVertex { position: [-0.5, 0.5, -0.5], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [0.5, 0.5, -0.5], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [-0.5, 0.5, 0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [0.5, 0.5, 0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
 ];
 const BOTTOM_FACE: &[MeshVertex] = &[
-    MeshVertex { position: [-0.5, -0.5, 0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [0.5, -0.5, 0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [-0.5, -0.5, -0.5], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [0.5, -0.5, -0.5], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
+    MeshVertex { position: [-0.5, -0.5, 0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [0.5, -0.5, 0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [-0.5, -0.5, -0.5], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [0.5, -0.5, -0.5], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
 ];
 const LEFT_FACE: &[MeshVertex] = &[
-    MeshVertex { position: [-0.5, 0.5, -0.5], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [-0.5, 0.5, 0.5], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [-0.5, -0.5, -0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [-0.5, -0.5, 0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
+    MeshVertex { position: [-0.5, 0.5, -0.5], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [-0.5, 0.5, 0.5], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [-0.5, -0.5, -0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [-0.5, -0.5, 0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
 ];
 const RIGHT_FACE: &[MeshVertex] = &[
-    MeshVertex { position: [0.5, 0.5, 0.5], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [0.5, 0.5, -0.5], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [0.5, -0.5, 0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
-    MeshVertex { position: [0.5, -0.5, -0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0 },
+    MeshVertex { position: [0.5, 0.5, 0.5], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [0.5, 0.5, -0.5], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [0.5, -0.5, 0.5], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
+    MeshVertex { position: [0.5, -0.5, -0.5], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 0.0], ao: 0.0, layer: 0 },
 ];
 
 impl BlockFace {
@@ -111,6 +426,92 @@ impl BlockFace {
         BLOCK_FACES.iter()
     }
 
+    // 0 for x, 1 for y, 2 for z
+    pub fn axis(&self) -> usize {
+        match *self {
+            BlockFace::Left | BlockFace::Right => 0,
+            BlockFace::Top | BlockFace::Bottom => 1,
+            BlockFace::Front | BlockFace::Back => 2,
+        }
+    }
+
+    // unit vector pointing out of the face
+    pub fn normal(&self) -> Vector3<i32> {
+        match *self {
+            BlockFace::Front => vector![0, 0, 1],
+            BlockFace::Back => vector![0, 0, -1],
+            BlockFace::Top => vector![0, 1, 0],
+            BlockFace::Bottom => vector![0, -1, 0],
+            BlockFace::Left => vector![-1, 0, 0],
+            BlockFace::Right => vector![1, 0, 0],
+        }
+    }
+
+    // texture coordinates of a point on the face, relative to the block's center, matching the
+    // face's vertices
+    pub fn uv(&self, p: Vector3<f32>) -> [f32; 2] {
+        match *self {
+            BlockFace::Front => [p.x + 0.5, 0.5 - p.y],
+            BlockFace::Back => [0.5 - p.x, 0.5 - p.y],
+            BlockFace::Top | BlockFace::Bottom => [p.x + 0.5, p.z + 0.5],
+            BlockFace::Left => [p.z + 0.5, 0.5 - p.y],
+            BlockFace::Right => [0.5 - p.z, 0.5 - p.y],
+        }
+    }
+
+    pub fn opposite(&self) -> Self {
+        self.mirrored(self.axis())
+    }
+
+    // for a block oriented towards self, the face of the upright block that ends up facing face
+    pub fn model_face(&self, face: BlockFace) -> BlockFace {
+        match *self {
+            BlockFace::Top => face,
+            BlockFace::Bottom => match face {
+                BlockFace::Left | BlockFace::Right => face,
+                _ => face.opposite(),
+            },
+            // tipped over towards self, around the horizontal axis at a right angle to it
+            orientation => if face == orientation {
+                BlockFace::Top
+            } else if face == orientation.opposite() {
+                BlockFace::Bottom
+            } else if face == BlockFace::Top {
+                orientation.opposite()
+            } else if face == BlockFace::Bottom {
+                orientation
+            } else {
+                face
+            },
+        }
+    }
+
+    // the face pointing this way after a quarter turn around y, +x turns towards +z
+    pub fn rotated_y(&self) -> Self {
+        match *self {
+            BlockFace::Right => BlockFace::Front,
+            BlockFace::Front => BlockFace::Left,
+            BlockFace::Left => BlockFace::Back,
+            BlockFace::Back => BlockFace::Right,
+            face => face,
+        }
+    }
+
+    // the face pointing this way after flipping along axis
+    pub fn mirrored(&self, axis: usize) -> Self {
+        if self.axis() != axis {
+            return *self;
+        }
+        match *self {
+            BlockFace::Left => BlockFace::Right,
+            BlockFace::Right => BlockFace::Left,
+            BlockFace::Top => BlockFace::Bottom,
+            BlockFace::Bottom => BlockFace::Top,
+            BlockFace::Front => BlockFace::Back,
+            BlockFace::Back => BlockFace::Front,
+        }
+    }
+
     pub fn get_vertices(&self) -> &[MeshVertex] {
         match *self {
             BlockFace::Front => FRONT_FACE,
@@ -123,3 +524,56 @@ impl BlockFace {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the blocks the engine needs, then extra
+    fn registry_with(extra: &str) -> Result<BlockRegistry> {
+        let builtin: String = BlockType::BUILTIN.iter().map(|name| format!("(name: {:?}),", name)).collect();
+        BlockRegistry::from_ron(&format!("#![enable(implicit_some)] [{}{}]", builtin, extra), block_textures())
+    }
+
+    #[test]
+    fn builtin_ids_match_their_names() {
+        let builtin = [
+            BlockType::AIR, BlockType::GRASS, BlockType::DIRT, BlockType::STONE,
+            BlockType::SAND, BlockType::WOOD, BlockType::LEAVES, BlockType::WATER,
+        ];
+        assert_eq!(builtin.len(), BlockType::BUILTIN.len());
+        for (block, name) in builtin.into_iter().zip(BlockType::BUILTIN) {
+            assert_eq!(block.name(), *name);
+            assert_eq!(BlockType::from_name(name), Some(block));
+        }
+    }
+
+    #[test]
+    fn builtin_blocks_come_first() {
+        assert!(registry_with("").is_ok());
+        assert!(BlockRegistry::from_ron(r#"[(name: "grass"), (name: "air")]"#, block_textures()).is_err());
+        // all of them
+        assert!(BlockRegistry::from_ron(r#"[(name: "air")]"#, block_textures()).is_err());
+    }
+
+    #[test]
+    fn names_are_unique() {
+        assert!(registry_with(r#"(name: "glass"), (name: "glass")"#).is_err());
+        assert!(registry_with(r#"(name: "stone")"#).is_err());
+    }
+
+    #[test]
+    fn unknown_textures_are_missing() {
+        let registry = registry_with(r#"(name: "glass", textures: (all: "no_such_texture", top: "stone"))"#).unwrap();
+        let glass = registry.get(registry.by_name("glass").unwrap());
+        let textures = block_textures();
+        assert_eq!(glass.textures[BlockFace::Front as usize], textures.id(MISSING_TEXTURE).unwrap());
+        assert_eq!(glass.textures[BlockFace::Top as usize], textures.id("stone").unwrap());
+    }
+
+    #[test]
+    fn light_is_at_most_15() {
+        assert!(registry_with(r#"(name: "lamp", light: 15)"#).is_ok());
+        assert!(registry_with(r#"(name: "lamp", light: 16)"#).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use crate::block::BlockType;
+use serde::Deserialize;
+
+pub const MAX_STACK: u16 = 64;
+pub const MAX_SIGN_TEXT: usize = 256; // bytes
+
+// The block entity a block gets when it is placed, named in blocks.ron
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum BlockEntityKind {
+    Container(u16), // number of slots
+    Sign,
+}
+
+impl BlockEntityKind {
+    pub fn create(&self) -> BlockEntity {
+        match *self {
+            BlockEntityKind::Container(slots) => BlockEntity::Container(Container::new(slots as usize)),
+            BlockEntityKind::Sign => BlockEntity::Sign(Sign::default()),
+        }
+    }
+}
+
+// Data a block carries beyond its type and state. Chunks own the entities of their blocks, they
+// are saved with the chunk and dropped when the block is replaced.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockEntity {
+    Container(Container),
+    Sign(Sign),
+}
+
+impl BlockEntity {
+    // rough number of bytes the entity takes up in memory
+    pub fn memory_size(&self) -> usize {
+        std::mem::size_of::<Self>() + match self {
+            BlockEntity::Container(container) => container.slots.len() * std::mem::size_of::<Option<ItemStack>>(),
+            BlockEntity::Sign(sign) => sign.text.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ItemStack {
+    pub block: BlockType,
+    pub count: u16, // 1 to MAX_STACK
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Container {
+    pub slots: Vec<Option<ItemStack>>,
+}
+
+impl Container {
+    pub fn new(slots: usize) -> Self {
+        Self {
+            slots: vec![None; slots],
+        }
+    }
+
+    // tops up stacks of the same block first, then fills empty slots. Returns how many didn't fit.
+    pub fn add(&mut self, block: BlockType, mut count: u16) -> u16 {
+        for slot in self.slots.iter_mut().flatten() {
+            if slot.block == block {
+                let moved = count.min(MAX_STACK - slot.count);
+                slot.count += moved;
+                count -= moved;
+            }
+        }
+        for slot in self.slots.iter_mut() {
+            if count == 0 {
+                break;
+            }
+            if slot.is_none() {
+                let moved = count.min(MAX_STACK);
+                *slot = Some(ItemStack { block, count: moved });
+                count -= moved;
+            }
+        }
+        count
+    }
+
+    pub fn take(&mut self, slot: usize) -> Option<ItemStack> {
+        self.slots.get_mut(slot)?.take()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Sign {
+    text: String,
+}
+
+impl Sign {
+    // text past MAX_SIGN_TEXT bytes is cut off, at a character boundary
+    pub fn new(text: &str) -> Self {
+        let mut end = text.len().min(MAX_SIGN_TEXT);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        Self {
+            text: text[..end].to_string(),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_tops_up_stacks_before_empty_slots() {
+        let mut container = Container::new(3);
+        container.slots[1] = Some(ItemStack { block: BlockType::STONE, count: MAX_STACK - 4 });
+        assert_eq!(container.add(BlockType::STONE, 10), 0);
+        assert_eq!(container.slots, vec![
+            Some(ItemStack { block: BlockType::STONE, count: 6 }),
+            Some(ItemStack { block: BlockType::STONE, count: MAX_STACK }),
+            None,
+        ]);
+    }
+
+    #[test]
+    fn add_keeps_blocks_apart() {
+        let mut container = Container::new(2);
+        assert_eq!(container.add(BlockType::STONE, 1), 0);
+        assert_eq!(container.add(BlockType::DIRT, 1), 0);
+        assert_eq!(container.slots, vec![
+            Some(ItemStack { block: BlockType::STONE, count: 1 }),
+            Some(ItemStack { block: BlockType::DIRT, count: 1 }),
+        ]);
+        assert_eq!(container.add(BlockType::SAND, 5), 5);
+    }
+
+    #[test]
+    fn add_returns_what_does_not_fit() {
+        let mut container = Container::new(2);
+        assert_eq!(container.add(BlockType::STONE, MAX_STACK * 3 + 1), MAX_STACK + 1);
+        assert!(container.slots.iter().all(|slot| *slot == Some(ItemStack { block: BlockType::STONE, count: MAX_STACK })));
+        assert_eq!(container.add(BlockType::STONE, 1), 1);
+    }
+
+    #[test]
+    fn take_empties_the_slot() {
+        let mut container = Container::new(2);
+        container.add(BlockType::STONE, 3);
+        assert_eq!(container.take(0), Some(ItemStack { block: BlockType::STONE, count: 3 }));
+        assert_eq!(container.take(0), None);
+        assert_eq!(container.take(5), None);
+        // the emptied slot is filled again
+        container.add(BlockType::DIRT, 1);
+        assert_eq!(container.slots[0], Some(ItemStack { block: BlockType::DIRT, count: 1 }));
+    }
+
+    #[test]
+    fn sign_text_is_capped() {
+        assert_eq!(Sign::new("hello").text(), "hello");
+        let long = "a".repeat(MAX_SIGN_TEXT + 10);
+        assert_eq!(Sign::new(&long).text().len(), MAX_SIGN_TEXT);
+        // the cut doesn't split a character
+        let wide = format!("a{}", "é".repeat(MAX_SIGN_TEXT));
+        let text = Sign::new(&wide).text().to_string();
+        assert_eq!(text.len(), MAX_SIGN_TEXT - 1);
+        assert!(text.ends_with('é'));
+    }
+}
@@ -0,0 +1,107 @@
+use image::{Rgba, RgbaImage, imageops::{self, FilterType}};
+use std::{collections::HashMap, sync::OnceLock};
+
+// (name, png) for every file in src/textures, listed by build.rs
+const TEXTURES: &[(&str, &[u8])] = include!(concat!(env!("OUT_DIR"), "/textures.rs"));
+
+pub const MISSING_TEXTURE: &str = "missing";
+const MISSING_SIZE: u32 = 16;
+// Layers are at least this big. Anisotropic filtering needs linear magnification, which blurs
+// small textures up close, so they are scaled up with nearest first to keep their pixels sharp.
+const MIN_LAYER_SIZE: u32 = 64;
+
+// Every block texture as one layer of a texture array, each with its mip chain. Textures are
+// referred to by layer index, with layer 0 being the generated missing texture.
+pub struct BlockTextures {
+    size: u32,
+    layers: Vec<Vec<RgbaImage>>,
+    ids: HashMap<String, u32>,
+}
+
+impl BlockTextures {
+    // textures of different sizes (or that aren't square) are all scaled to the size of the
+    // biggest one, rounded up to a power of two
+    pub fn new(mut textures: Vec<(String, RgbaImage)>) -> Self {
+        textures.retain(|(name, _)| name != MISSING_TEXTURE);
+        textures.insert(0, (MISSING_TEXTURE.to_string(), missing_texture()));
+
+        let size = textures.iter()
+            .map(|(_, t)| t.width().max(t.height()))
+            .max()
+            .unwrap()
+            .max(MIN_LAYER_SIZE)
+            .next_power_of_two();
+
+        let mut layers = Vec::with_capacity(textures.len());
+        let mut ids = HashMap::new();
+        for (i, (name, texture)) in textures.into_iter().enumerate() {
+            let texture = if texture.dimensions() == (size, size) {
+                texture
+            } else {
+                imageops::resize(&texture, size, size, FilterType::Nearest)
+            };
+            layers.push(mip_chain(texture));
+            ids.insert(name, i as u32);
+        }
+
+        Self {
+            size,
+            layers,
+            ids,
+        }
+    }
+
+    pub fn id(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).copied()
+    }
+
+    // width and height of every layer
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    // mip chain of every layer, full size first
+    pub fn layers(&self) -> &[Vec<RgbaImage>] {
+        &self.layers
+    }
+}
+
+// halves the texture down to 1x1
+fn mip_chain(texture: RgbaImage) -> Vec<RgbaImage> {
+    let mut mips = vec![texture];
+    loop {
+        let last = mips.last().unwrap();
+        if last.width() == 1 {
+            return mips;
+        }
+        let (width, height) = (last.width() / 2, last.height() / 2);
+        mips.push(imageops::resize(last, width, height, FilterType::Triangle));
+    }
+}
+
+// magenta and black checkerboard
+fn missing_texture() -> RgbaImage {
+    RgbaImage::from_fn(MISSING_SIZE, MISSING_SIZE, |x, y| {
+        if (x < MISSING_SIZE / 2) == (y < MISSING_SIZE / 2) {
+            Rgba([255, 0, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    })
+}
+
+static BLOCK_TEXTURES: OnceLock<BlockTextures> = OnceLock::new();
+
+// the textures in src/textures, ones that fail to decode are left out
+pub fn block_textures() -> &'static BlockTextures {
+    BLOCK_TEXTURES.get_or_init(|| {
+        let mut textures = Vec::new();
+        for (name, bytes) in TEXTURES {
+            match image::load_from_memory(bytes) {
+                Ok(texture) => textures.push((name.to_string(), texture.to_rgba8())),
+                Err(e) => eprintln!("failed to load texture {}: {:?}", name, e),
+            }
+        }
+        BlockTextures::new(textures)
+    })
+}
@@ -1,35 +1,378 @@
-use crate::block::BlockType;
+use crate::block::{BlockType, BlockState};
+use crate::block_entity::{BlockEntity, Container, ItemStack, Sign, MAX_STACK};
+use crate::coords::LocalPos;
 use nalgebra::{Vector3, vector};
+use anyhow::{Result, anyhow, bail};
+use std::{
+    collections::HashMap,
+    io::Read,
+};
 
 pub const CHUNK_SIZE: usize = 32;
+pub const CHUNK_VOLUME: usize = CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE;
 
+const CHUNK_FORMAT_VERSION: u8 = 4;
+
+type Migration = fn(&[u8]) -> Result<Vec<u8>>;
+
+// MIGRATIONS[v - 1] upgrades the encoding of format version v to version v + 1
+const MIGRATIONS: &[Migration] = &[
+    migrate_v1,
+    migrate_v2,
+    migrate_v3,
+];
+
+// palette indices packed into u64 words, entries never straddle two words
+#[derive(Clone)]
+struct PackedIndices {
+    bits: usize,
+    words: Vec<u64>,
+}
+
+impl PackedIndices {
+    fn new(bits: usize) -> Self {
+        Self {
+            bits,
+            words: vec![0; CHUNK_VOLUME * bits / 64],
+        }
+    }
+
+    #[inline]
+    fn get(&self, i: usize) -> usize {
+        let per_word = 64 / self.bits;
+        let shift = (i % per_word) * self.bits;
+        ((self.words[i / per_word] >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+
+    #[inline]
+    fn set(&mut self, i: usize, value: usize) {
+        let per_word = 64 / self.bits;
+        let shift = (i % per_word) * self.bits;
+        let mask = ((1u64 << self.bits) - 1) << shift;
+        let word = &mut self.words[i / per_word];
+        *word = (*word & !mask) | ((value as u64) << shift);
+    }
+
+    fn with_bits(&self, bits: usize) -> Self {
+        let mut resized = Self::new(bits);
+        for i in 0..CHUNK_VOLUME {
+            resized.set(i, self.get(i));
+        }
+        resized
+    }
+}
+
+fn read_u8(bytes: &mut &[u8]) -> Result<u8> {
+    let mut buf = [0; 1];
+    bytes.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16(bytes: &mut &[u8]) -> Result<u16> {
+    let mut buf = [0; 2];
+    bytes.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32(bytes: &mut &[u8]) -> Result<u32> {
+    let mut buf = [0; 4];
+    bytes.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(bytes: &mut &[u8]) -> Result<u64> {
+    let mut buf = [0; 8];
+    bytes.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_name(bytes: &mut &[u8]) -> Result<String> {
+    let len = read_u8(bytes)? as usize;
+    let mut buf = vec![0; len];
+    bytes.read_exact(&mut buf)?;
+    Ok(String::from_utf8(buf)?)
+}
+
+fn write_name(bytes: &mut Vec<u8>, name: &str) {
+    bytes.push(name.len() as u8);
+    bytes.extend_from_slice(name.as_bytes());
+}
+
+// Version 1 stored each palette entry as the BlockType discriminant, in the variant order below.
+// Version 2 adds the block id table, so the ids are carried over as is with the v1 names.
+fn migrate_v1(bytes: &[u8]) -> Result<Vec<u8>> {
+    const V1_BLOCK_NAMES: [&str; 8] = ["air", "grass", "dirt", "stone", "sand", "wood", "leaves", "water"];
+
+    let mut bytes = &bytes[1..];
+    let palette_len = read_u16(&mut bytes)?;
+    let mut palette = Vec::with_capacity(palette_len as usize);
+    for _ in 0..palette_len {
+        palette.push(read_u8(&mut bytes)?);
+    }
+
+    let mut ids = palette.clone();
+    ids.sort();
+    ids.dedup();
+
+    let mut migrated = vec![2];
+    migrated.extend_from_slice(&(ids.len() as u16).to_le_bytes());
+    for id in ids {
+        let name = V1_BLOCK_NAMES.get(id as usize).ok_or_else(|| anyhow!("unknown v1 block id {}", id))?;
+        migrated.extend_from_slice(&(id as u16).to_le_bytes());
+        write_name(&mut migrated, name);
+    }
+    migrated.extend_from_slice(&palette_len.to_le_bytes());
+    for id in palette {
+        migrated.extend_from_slice(&(id as u16).to_le_bytes());
+    }
+    // index width and words are unchanged
+    migrated.extend_from_slice(bytes);
+    Ok(migrated)
+}
+
+// Version 3 appends the block states, version 2 chunks have none.
+fn migrate_v2(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut migrated = bytes.to_vec();
+    migrated[0] = 3;
+    migrated.extend_from_slice(&0u32.to_le_bytes());
+    Ok(migrated)
+}
+
+// Version 4 appends the block entities, version 3 chunks have none.
+fn migrate_v3(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut migrated = bytes.to_vec();
+    migrated[0] = 4;
+    migrated.extend_from_slice(&0u32.to_le_bytes());
+    Ok(migrated)
+}
+
+const CONTAINER_ENTITY: u8 = 0;
+const SIGN_ENTITY: u8 = 1;
+
+fn write_entity(bytes: &mut Vec<u8>, entity: &BlockEntity) {
+    match entity {
+        BlockEntity::Container(container) => {
+            bytes.push(CONTAINER_ENTITY);
+            bytes.extend_from_slice(&(container.slots.len() as u16).to_le_bytes());
+            for slot in &container.slots {
+                match slot {
+                    Some(stack) => {
+                        bytes.extend_from_slice(&stack.count.to_le_bytes());
+                        write_name(bytes, stack.block.name());
+                    },
+                    None => bytes.extend_from_slice(&0u16.to_le_bytes()),
+                }
+            }
+        },
+        BlockEntity::Sign(sign) => {
+            bytes.push(SIGN_ENTITY);
+            // signs keep their text under MAX_SIGN_TEXT, which fits the length
+            bytes.extend_from_slice(&(sign.text().len() as u16).to_le_bytes());
+            bytes.extend_from_slice(sign.text().as_bytes());
+        },
+    }
+}
+
+fn read_entity(bytes: &mut &[u8]) -> Result<BlockEntity> {
+    match read_u8(bytes)? {
+        CONTAINER_ENTITY => {
+            let len = read_u16(bytes)?;
+            let mut slots = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                let count = read_u16(bytes)?;
+                if count > MAX_STACK {
+                    bail!("stack of {} is over the limit of {}", count, MAX_STACK);
+                }
+                slots.push(if count == 0 {
+                    None
+                } else {
+                    let name = read_name(bytes)?;
+                    let block = BlockType::from_name(&name).ok_or_else(|| anyhow!("unknown block '{}'", name))?;
+                    Some(ItemStack { block, count })
+                });
+            }
+            Ok(BlockEntity::Container(Container { slots }))
+        },
+        SIGN_ENTITY => {
+            let mut buf = vec![0; read_u16(bytes)? as usize];
+            bytes.read_exact(&mut buf)?;
+            Ok(BlockEntity::Sign(Sign::new(&String::from_utf8(buf)?)))
+        },
+        kind => bail!("unknown block entity kind {}", kind),
+    }
+}
+
+// smallest supported index width that can address `len` palette entries
+fn bits_for(len: usize) -> usize {
+    let mut bits = 1;
+    while (1 << bits) < len {
+        bits *= 2;
+    }
+    bits
+}
+
+// Blocks are stored as a palette of the distinct block types in the chunk plus a packed index
+// per block. Chunks made of a single block type keep only the palette. Block states are sparse,
+// blocks with the default state have no entry, and so are block entities.
 #[derive(Clone)]
 pub struct Chunk {
-    pub blocks: [BlockType; CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE],
+    palette: Vec<BlockType>,
+    counts: Vec<usize>, // number of blocks using each palette entry, 0 means the slot is free
+    indices: Option<PackedIndices>,
+    states: HashMap<u16, BlockState>,
+    entities: HashMap<u16, BlockEntity>,
 }
 
 impl Chunk {
-    pub fn new() -> Self {
-        let blocks: [BlockType; CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE]
-            = [BlockType::Air; CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE];
+    pub fn filled(block: BlockType) -> Self {
+        Self {
+            palette: vec![block],
+            counts: vec![CHUNK_VOLUME],
+            indices: None,
+            states: HashMap::new(),
+            entities: HashMap::new(),
+        }
+    }
+
+    pub fn from_blocks(blocks: &[BlockType]) -> Self {
+        assert_eq!(blocks.len(), CHUNK_VOLUME);
+        let mut palette: Vec<BlockType> = Vec::new();
+        let mut counts: Vec<usize> = Vec::new();
+        let mut raw_indices: Vec<usize> = Vec::with_capacity(CHUNK_VOLUME);
+        for block in blocks {
+            let p = match palette.iter().position(|b| b == block) {
+                Some(p) => p,
+                None => {
+                    palette.push(*block);
+                    counts.push(0);
+                    palette.len() - 1
+                },
+            };
+            counts[p] += 1;
+            raw_indices.push(p);
+        }
+
+        if palette.len() == 1 {
+            return Self::filled(palette[0]);
+        }
+
+        let mut indices = PackedIndices::new(bits_for(palette.len()));
+        for (i, p) in raw_indices.into_iter().enumerate() {
+            indices.set(i, p);
+        }
 
         Self {
-            blocks,
+            palette,
+            counts,
+            indices: Some(indices),
+            states: HashMap::new(),
+            entities: HashMap::new(),
         }
     }
 
     #[inline]
-    pub fn get_block(&self, position: Vector3<usize>) -> BlockType {
-        self.blocks[position.x + CHUNK_SIZE*position.y + CHUNK_SIZE*CHUNK_SIZE*position.z]
+    fn palette_index(&self, i: usize) -> usize {
+        match &self.indices {
+            Some(indices) => indices.get(i),
+            None => 0,
+        }
+    }
+
+    // finds the palette entry for block, adding one (and widening the indices if needed)
+    fn palette_entry(&mut self, block: BlockType) -> usize {
+        if let Some(p) = (0..self.palette.len()).find(|p| self.counts[*p] > 0 && self.palette[*p] == block) {
+            return p;
+        }
+        if let Some(p) = self.counts.iter().position(|c| *c == 0) {
+            self.palette[p] = block;
+            return p;
+        }
+
+        self.palette.push(block);
+        self.counts.push(0);
+        let bits = bits_for(self.palette.len());
+        self.indices = match self.indices.take() {
+            Some(indices) if indices.bits < bits => Some(indices.with_bits(bits)),
+            Some(indices) => Some(indices),
+            None => Some(PackedIndices::new(bits)),
+        };
+        self.palette.len() - 1
     }
 
     #[inline]
-    pub fn set_block(&mut self, new_block: BlockType, position: Vector3<usize>) {
-        self.blocks[position.x + CHUNK_SIZE*position.y + CHUNK_SIZE*CHUNK_SIZE*position.z] = new_block;
+    pub fn get_block(&self, position: LocalPos) -> BlockType {
+        self.palette[self.palette_index(position.index())]
+    }
+
+    // the new block starts out in the default state, and without the old block's entity
+    pub fn set_block(&mut self, new_block: BlockType, position: LocalPos) {
+        let i = position.index();
+        self.states.remove(&(i as u16));
+        let old = self.palette_index(i);
+        if self.palette[old] == new_block {
+            return;
+        }
+        self.entities.remove(&(i as u16));
+
+        let new = self.palette_entry(new_block);
+        if let Some(indices) = &mut self.indices {
+            indices.set(i, new);
+        }
+        self.counts[new] += 1;
+        self.counts[old] -= 1;
+
+        if self.counts[old] == 0 && self.counts.iter().filter(|c| **c > 0).count() == 1 {
+            self.palette = vec![new_block];
+            self.counts = vec![CHUNK_VOLUME];
+            self.indices = None;
+        }
+    }
+
+    #[inline]
+    pub fn get_state(&self, position: LocalPos) -> BlockState {
+        self.states.get(&(position.index() as u16)).copied().unwrap_or_default()
+    }
+
+    pub fn set_state(&mut self, state: BlockState, position: LocalPos) {
+        let i = position.index() as u16;
+        if state == BlockState::default() {
+            self.states.remove(&i);
+        } else {
+            self.states.insert(i, state);
+        }
+    }
+
+    pub fn get_entity(&self, position: LocalPos) -> Option<&BlockEntity> {
+        self.entities.get(&(position.index() as u16))
+    }
+
+    pub fn get_entity_mut(&mut self, position: LocalPos) -> Option<&mut BlockEntity> {
+        self.entities.get_mut(&(position.index() as u16))
+    }
+
+    pub fn set_entity(&mut self, entity: Option<BlockEntity>, position: LocalPos) {
+        let i = position.index() as u16;
+        match entity {
+            Some(entity) => self.entities.insert(i, entity),
+            None => self.entities.remove(&i),
+        };
     }
 
     #[inline]
     pub fn get_block_border(&self, neighbors: &[Chunk], position: Vector3<i32>) -> BlockType {
+        let (chunk, block_pos) = self.border(neighbors, position);
+        chunk.get_block(block_pos)
+    }
+
+    #[inline]
+    pub fn get_state_border(&self, neighbors: &[Chunk], position: Vector3<i32>) -> BlockState {
+        let (chunk, block_pos) = self.border(neighbors, position);
+        chunk.get_state(block_pos)
+    }
+
+    // the chunk holding position, which may be up to a block outside this one, and where in it
+    #[inline]
+    fn border<'a>(&'a self, neighbors: &'a [Chunk], position: Vector3<i32>) -> (&'a Chunk, LocalPos) {
         let mut n = vector![1, 1, 1];
         let mut b = position;
         let max_b = (CHUNK_SIZE-1) as i32;
@@ -40,14 +383,14 @@ impl Chunk {
         } else if position.y < 0 {
             n.y = 0;
             b.y = max_b;
-        } 
+        }
         if position.z > max_b {
             n.z = 2;
             b.z = 0;
         } else if position.z < 0 {
             n.z = 0;
             b.z = max_b;
-        } 
+        }
         if position.x > max_b {
             n.x = 2;
             b.x = 0;
@@ -56,14 +399,372 @@ impl Chunk {
             b.x = max_b;
         }
 
+        let block_pos = LocalPos::new(b.x as usize, b.y as usize, b.z as usize);
         if n.x == 1 && n.y == 1 && n.z == 1 {
-            self.get_block(b.try_cast::<usize>().unwrap())
+            (self, block_pos)
+        } else {
+            (&neighbors[n.x + n.y*3 + 3*3*n.z], block_pos)
+        }
+    }
+
+    // blocks in storage order (x fastest, then y, then z)
+    pub fn iter(&self) -> impl Iterator<Item = BlockType> + '_ {
+        (0..CHUNK_VOLUME).map(|i| self.palette[self.palette_index(i)])
+    }
+
+    // the block filling the whole chunk, if there is only one
+    pub fn uniform_block(&self) -> Option<BlockType> {
+        match self.indices {
+            Some(_) => None,
+            None => Some(self.palette[0]),
+        }
+    }
+
+    // rough number of bytes the chunk takes up in memory
+    pub fn memory_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.palette.len() * std::mem::size_of::<BlockType>()
+            + self.counts.len() * std::mem::size_of::<usize>()
+            + self.indices.as_ref().map_or(0, |indices| indices.words.len() * 8)
+            + self.states.len() * (std::mem::size_of::<u16>() + std::mem::size_of::<BlockState>())
+            + self.entities.values().map(|entity| std::mem::size_of::<u16>() + entity.memory_size()).sum::<usize>()
+    }
+
+    // Layout, little endian:
+    //   format version (u8)
+    //   block id table: entry count (u16), then per entry the id (u16) and block name (u8 length + utf8)
+    //   palette: length (u16), then the id of each entry (u16)
+    //   for palettes with more than one entry: index width in bits (u8) and the packed index words (u64)
+    //   block states: count (u32), then per state the block index (u16) and state (u16)
+    //   block entities: count (u32), then per entity the block index (u16), kind (u8) and its data:
+    //     container: slot count (u16), then per slot the stack size (u16, 0 for empty) and for
+    //       stacks the block name
+    //     sign: text (u16 length + utf8)
+    // Ids are only meaningful through the table, loading goes by name.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut table: Vec<BlockType> = Vec::new();
+        let mut palette_ids: Vec<u16> = Vec::with_capacity(self.palette.len());
+        for block in &self.palette {
+            let id = match table.iter().position(|b| b == block) {
+                Some(id) => id,
+                None => {
+                    table.push(*block);
+                    table.len() - 1
+                },
+            };
+            palette_ids.push(id as u16);
+        }
+
+        let mut bytes = vec![CHUNK_FORMAT_VERSION];
+        bytes.extend_from_slice(&(table.len() as u16).to_le_bytes());
+        for (id, block) in table.iter().enumerate() {
+            bytes.extend_from_slice(&(id as u16).to_le_bytes());
+            write_name(&mut bytes, block.name());
+        }
+        bytes.extend_from_slice(&(palette_ids.len() as u16).to_le_bytes());
+        for id in palette_ids {
+            bytes.extend_from_slice(&id.to_le_bytes());
+        }
+        if let Some(indices) = &self.indices {
+            bytes.push(indices.bits as u8);
+            for word in &indices.words {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        let mut states: Vec<(&u16, &BlockState)> = self.states.iter().collect();
+        states.sort_by_key(|(i, _)| **i);
+        bytes.extend_from_slice(&(states.len() as u32).to_le_bytes());
+        for (i, state) in states {
+            bytes.extend_from_slice(&i.to_le_bytes());
+            bytes.extend_from_slice(&state.0.to_le_bytes());
+        }
+        let mut entities: Vec<(&u16, &BlockEntity)> = self.entities.iter().collect();
+        entities.sort_by_key(|(i, _)| **i);
+        bytes.extend_from_slice(&(entities.len() as u32).to_le_bytes());
+        for (i, entity) in entities {
+            bytes.extend_from_slice(&i.to_le_bytes());
+            write_entity(&mut bytes, entity);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut version = *bytes.first().ok_or_else(|| anyhow!("chunk data is empty"))?;
+        if version == 0 || version > CHUNK_FORMAT_VERSION {
+            bail!("unsupported chunk format version {}", version);
+        }
+        let mut migrated;
+        let mut bytes = bytes;
+        while version < CHUNK_FORMAT_VERSION {
+            migrated = MIGRATIONS[version as usize - 1](bytes)?;
+            bytes = &migrated;
+            version += 1;
+        }
+        bytes = &bytes[1..];
+
+        let table_len = read_u16(&mut bytes)?;
+        let mut table: HashMap<u16, BlockType> = HashMap::new();
+        for _ in 0..table_len {
+            let id = read_u16(&mut bytes)?;
+            let name = read_name(&mut bytes)?;
+            let block = BlockType::from_name(&name).ok_or_else(|| anyhow!("unknown block '{}'", name))?;
+            table.insert(id, block);
+        }
+
+        let palette_len = read_u16(&mut bytes)? as usize;
+        if palette_len == 0 {
+            bail!("chunk has an empty palette");
+        }
+        let mut palette = Vec::with_capacity(palette_len);
+        for _ in 0..palette_len {
+            let id = read_u16(&mut bytes)?;
+            palette.push(*table.get(&id).ok_or_else(|| anyhow!("block id {} missing from the id table", id))?);
+        }
+        let mut chunk = if palette_len == 1 {
+            Self::filled(palette[0])
         } else {
-            neighbors[n.x + n.y*3 + 3*3*n.z].get_block(b.try_cast::<usize>().unwrap())
+            Self::read_indices(&mut bytes, palette)?
+        };
+
+        let state_count = read_u32(&mut bytes)?;
+        for _ in 0..state_count {
+            let i = read_u16(&mut bytes)?;
+            let state = BlockState(read_u16(&mut bytes)?);
+            if i as usize >= CHUNK_VOLUME {
+                bail!("block state index {} out of range", i);
+            }
+            chunk.states.insert(i, state);
         }
+
+        let entity_count = read_u32(&mut bytes)?;
+        for _ in 0..entity_count {
+            let i = read_u16(&mut bytes)?;
+            if i as usize >= CHUNK_VOLUME {
+                bail!("block entity index {} out of range", i);
+            }
+            chunk.entities.insert(i, read_entity(&mut bytes)?);
+        }
+        Ok(chunk)
     }
 
-    pub fn set(&mut self, blocks: [BlockType; CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE]) {
-        self.blocks = blocks;
+    fn read_indices(bytes: &mut &[u8], palette: Vec<BlockType>) -> Result<Self> {
+        let bits = read_u8(bytes)? as usize;
+        if ![1, 2, 4, 8, 16].contains(&bits) || (1 << bits) < palette.len() {
+            bail!("invalid index width {} for a palette of {}", bits, palette.len());
+        }
+        let mut indices = PackedIndices::new(bits);
+        for word in indices.words.iter_mut() {
+            *word = read_u64(bytes)?;
+        }
+
+        let mut counts = vec![0; palette.len()];
+        for i in 0..CHUNK_VOLUME {
+            let p = indices.get(i);
+            if p >= palette.len() {
+                bail!("palette index {} out of range", p);
+            }
+            counts[p] += 1;
+        }
+        if let Some(p) = counts.iter().position(|c| *c == CHUNK_VOLUME) {
+            return Ok(Self::filled(palette[p]));
+        }
+
+        Ok(Self {
+            palette,
+            counts,
+            indices: Some(indices),
+            states: HashMap::new(),
+            entities: HashMap::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockFace;
+
+    const ALL_BLOCKS: [BlockType; 8] = [
+        BlockType::AIR, BlockType::GRASS, BlockType::DIRT, BlockType::STONE,
+        BlockType::SAND, BlockType::WOOD, BlockType::LEAVES, BlockType::WATER,
+    ];
+
+    // deterministic mix of every block type
+    fn mixed_chunk() -> Chunk {
+        let mut state: u32 = 12345;
+        let blocks: Vec<BlockType> = (0..CHUNK_VOLUME).map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            ALL_BLOCKS[(state >> 24) as usize % ALL_BLOCKS.len()]
+        }).collect();
+        Chunk::from_blocks(&blocks)
+    }
+
+    fn assert_same_blocks(a: &Chunk, b: &Chunk) {
+        assert!(a.iter().eq(b.iter()));
+    }
+
+    #[test]
+    fn round_trip_uniform() {
+        for block in ALL_BLOCKS {
+            let chunk = Chunk::filled(block);
+            let loaded = Chunk::from_bytes(&chunk.to_bytes()).unwrap();
+            assert_eq!(loaded.uniform_block(), Some(block));
+        }
+    }
+
+    #[test]
+    fn round_trip_mixed() {
+        let chunk = mixed_chunk();
+        let loaded = Chunk::from_bytes(&chunk.to_bytes()).unwrap();
+        assert_same_blocks(&chunk, &loaded);
+    }
+
+    #[test]
+    fn round_trip_after_edits() {
+        // removing every block of a type leaves a free palette slot behind
+        let mut chunk = Chunk::filled(BlockType::STONE);
+        chunk.set_block(BlockType::WATER, LocalPos::new(1, 2, 3));
+        chunk.set_block(BlockType::SAND, LocalPos::new(4, 5, 6));
+        chunk.set_block(BlockType::STONE, LocalPos::new(1, 2, 3));
+        let loaded = Chunk::from_bytes(&chunk.to_bytes()).unwrap();
+        assert_same_blocks(&chunk, &loaded);
+
+        chunk.set_block(BlockType::STONE, LocalPos::new(4, 5, 6));
+        let loaded = Chunk::from_bytes(&chunk.to_bytes()).unwrap();
+        assert_eq!(loaded.uniform_block(), Some(BlockType::STONE));
+    }
+
+    #[test]
+    fn round_trip_states() {
+        let mut chunk = mixed_chunk();
+        let state = BlockState::default().with_level(5).with_player_placed(true);
+        chunk.set_state(state, LocalPos::new(3, 4, 5));
+        chunk.set_state(BlockState::default().with_orientation(BlockFace::Left), LocalPos::new(31, 0, 31));
+
+        let loaded = Chunk::from_bytes(&chunk.to_bytes()).unwrap();
+        assert_eq!(loaded.get_state(LocalPos::new(3, 4, 5)), state);
+        assert_eq!(loaded.get_state(LocalPos::new(31, 0, 31)).orientation(), BlockFace::Left);
+        assert_eq!(loaded.get_state(LocalPos::new(0, 0, 0)), BlockState::default());
+    }
+
+    #[test]
+    fn set_block_resets_state() {
+        let mut chunk = Chunk::filled(BlockType::WATER);
+        chunk.set_state(BlockState::default().with_level(3), LocalPos::new(1, 1, 1));
+        chunk.set_block(BlockType::WATER, LocalPos::new(1, 1, 1));
+        assert_eq!(chunk.get_state(LocalPos::new(1, 1, 1)), BlockState::default());
+    }
+
+    #[test]
+    fn round_trip_entities() {
+        let mut chunk = mixed_chunk();
+        let mut container = Container::new(3);
+        container.add(BlockType::SAND, 70);
+        chunk.set_entity(Some(BlockEntity::Container(container.clone())), LocalPos::new(1, 2, 3));
+        let sign = BlockEntity::Sign(Sign::new("hello\nthere"));
+        chunk.set_entity(Some(sign.clone()), LocalPos::new(31, 31, 31));
+
+        let loaded = Chunk::from_bytes(&chunk.to_bytes()).unwrap();
+        assert_eq!(loaded.get_entity(LocalPos::new(1, 2, 3)), Some(&BlockEntity::Container(container)));
+        assert_eq!(loaded.get_entity(LocalPos::new(31, 31, 31)), Some(&sign));
+        assert_eq!(loaded.get_entity(LocalPos::new(0, 0, 0)), None);
+    }
+
+    #[test]
+    fn stacks_over_the_limit_fail() {
+        let mut chunk = mixed_chunk();
+        let mut container = Container::new(1);
+        container.slots[0] = Some(ItemStack { block: BlockType::SAND, count: MAX_STACK + 1 });
+        chunk.set_entity(Some(BlockEntity::Container(container)), LocalPos::new(1, 2, 3));
+        assert!(Chunk::from_bytes(&chunk.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn breaking_block_drops_entity() {
+        let mut chunk = Chunk::filled(BlockType::WOOD);
+        let sign = BlockEntity::Sign(Sign::new("keep"));
+        chunk.set_entity(Some(sign.clone()), LocalPos::new(1, 1, 1));
+        chunk.set_block(BlockType::WOOD, LocalPos::new(1, 1, 1));
+        assert_eq!(chunk.get_entity(LocalPos::new(1, 1, 1)), Some(&sign));
+        chunk.set_block(BlockType::AIR, LocalPos::new(1, 1, 1));
+        assert_eq!(chunk.get_entity(LocalPos::new(1, 1, 1)), None);
+    }
+
+    #[test]
+    fn loads_v3_without_entities() {
+        let chunk = mixed_chunk();
+        let mut bytes = chunk.to_bytes();
+        bytes.truncate(bytes.len() - 4);
+        bytes[0] = 3;
+        let loaded = Chunk::from_bytes(&bytes).unwrap();
+        assert_same_blocks(&chunk, &loaded);
+        assert!(loaded.entities.is_empty());
+    }
+
+    #[test]
+    fn loads_v1_fixture() {
+        let chunk = Chunk::from_bytes(include_bytes!("fixtures/chunk-v1.bin")).unwrap();
+        for i in 0..CHUNK_VOLUME {
+            let pos = LocalPos::from_index(i);
+            let expected = match (pos.x, pos.y, pos.z) {
+                (0, 0, 0) => BlockType::SAND,
+                (3, 25, 7) => BlockType::WATER,
+                (5, 20, 5) => BlockType::WOOD,
+                (5, 21, 5) => BlockType::LEAVES,
+                (_, 19, _) => BlockType::GRASS,
+                (_, 18, _) => BlockType::DIRT,
+                (_, y, _) if y >= 20 => BlockType::AIR,
+                _ => BlockType::STONE,
+            };
+            assert_eq!(chunk.get_block(pos), expected, "block at {:?}", pos);
+        }
+    }
+
+    #[test]
+    fn loads_v1_uniform_fixture() {
+        let chunk = Chunk::from_bytes(include_bytes!("fixtures/chunk-v1-uniform.bin")).unwrap();
+        assert_eq!(chunk.uniform_block(), Some(BlockType::WATER));
+    }
+
+    #[test]
+    fn ids_resolve_through_table() {
+        // ids 0 and 1 deliberately do not match the BlockType order
+        let mut bytes = vec![CHUNK_FORMAT_VERSION];
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        write_name(&mut bytes, "water");
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        write_name(&mut bytes, "leaves");
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.push(1);
+        let mut words = vec![0u64; CHUNK_VOLUME / 64];
+        words[0] = 1; // first block uses palette entry 1
+        for word in words {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+
+        let chunk = Chunk::from_bytes(&bytes).unwrap();
+        assert_eq!(chunk.get_block(LocalPos::new(0, 0, 0)), BlockType::WATER);
+        assert_eq!(chunk.get_block(LocalPos::new(1, 0, 0)), BlockType::LEAVES);
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        let bytes = mixed_chunk().to_bytes();
+        assert!(Chunk::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Chunk::from_bytes(&[]).is_err());
+        assert!(Chunk::from_bytes(&[CHUNK_FORMAT_VERSION + 1]).is_err());
+
+        let mut unknown = vec![CHUNK_FORMAT_VERSION];
+        unknown.extend_from_slice(&1u16.to_le_bytes());
+        unknown.extend_from_slice(&0u16.to_le_bytes());
+        write_name(&mut unknown, "not_a_block");
+        unknown.extend_from_slice(&1u16.to_le_bytes());
+        unknown.extend_from_slice(&0u16.to_le_bytes());
+        assert!(Chunk::from_bytes(&unknown).is_err());
     }
 }
@@ -0,0 +1,127 @@
+use crate::chunk::Chunk;
+use crate::coords::ChunkPos;
+use std::collections::{HashMap, VecDeque};
+
+// Edited chunks kept in memory after being unloaded, so coming back to them neither waits on the
+// disk nor regenerates them without the edits. Once the chunks take up more than the budget the
+// ones unloaded longest ago are dropped; whatever was saved to disk is still there.
+pub struct ChunkCache {
+    budget: usize, // bytes
+    used: usize,
+    chunks: HashMap<ChunkPos, Chunk>,
+    order: VecDeque<ChunkPos>, // least recently unloaded first
+}
+
+impl ChunkCache {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            used: 0,
+            chunks: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    // chunks bigger than the whole budget aren't kept, rather than pushing out everything else
+    pub fn insert(&mut self, chunk_pos: ChunkPos, chunk: Chunk) {
+        self.take(chunk_pos);
+        if chunk.memory_size() > self.budget {
+            return;
+        }
+        self.used += chunk.memory_size();
+        self.chunks.insert(chunk_pos, chunk);
+        self.order.push_back(chunk_pos);
+
+        while self.used > self.budget {
+            match self.order.pop_front() {
+                Some(oldest) => if let Some(chunk) = self.chunks.remove(&oldest) {
+                    self.used -= chunk.memory_size();
+                },
+                None => break,
+            }
+        }
+    }
+
+    // removes the chunk from the cache, the terrain owns it again once it's loaded
+    pub fn take(&mut self, chunk_pos: ChunkPos) -> Option<Chunk> {
+        let chunk = self.chunks.remove(&chunk_pos)?;
+        self.used -= chunk.memory_size();
+        self.order.retain(|c| *c != chunk_pos);
+        Some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockType;
+    use crate::chunk::CHUNK_VOLUME;
+
+    fn pos(x: i32) -> ChunkPos {
+        ChunkPos::new(x, 0, 0)
+    }
+
+    fn chunk() -> Chunk {
+        Chunk::filled(BlockType::STONE)
+    }
+
+    // room for two uniform chunks
+    fn cache() -> ChunkCache {
+        ChunkCache::new(2 * chunk().memory_size())
+    }
+
+    fn cached(cache: &ChunkCache) -> Vec<ChunkPos> {
+        let mut positions: Vec<_> = cache.chunks.keys().copied().collect();
+        positions.sort_by_key(|pos| pos.x);
+        positions
+    }
+
+    #[test]
+    fn evicts_least_recently_inserted() {
+        let mut cache = cache();
+        for x in 0..3 {
+            cache.insert(pos(x), chunk());
+        }
+        assert_eq!(cached(&cache), [pos(1), pos(2)]);
+        assert_eq!(cache.used, 2 * chunk().memory_size());
+    }
+
+    #[test]
+    fn reinserting_makes_a_chunk_recent() {
+        let mut cache = cache();
+        cache.insert(pos(0), chunk());
+        cache.insert(pos(1), chunk());
+        cache.insert(pos(0), chunk());
+        cache.insert(pos(2), chunk());
+        assert_eq!(cached(&cache), [pos(0), pos(2)]);
+    }
+
+    #[test]
+    fn taking_frees_the_budget() {
+        let mut cache = cache();
+        cache.insert(pos(0), chunk());
+        cache.insert(pos(1), chunk());
+        assert!(cache.take(pos(0)).is_some());
+        assert!(cache.take(pos(0)).is_none());
+        cache.insert(pos(2), chunk());
+        assert_eq!(cached(&cache), [pos(1), pos(2)]);
+        // taken and put back, it's the most recent now
+        let taken = cache.take(pos(1)).unwrap();
+        cache.insert(pos(1), taken);
+        cache.insert(pos(3), chunk());
+        assert_eq!(cached(&cache), [pos(1), pos(3)]);
+    }
+
+    #[test]
+    fn chunks_over_budget_are_not_kept() {
+        let mut cache = cache();
+        cache.insert(pos(0), chunk());
+        let big = Chunk::from_blocks(&(0..CHUNK_VOLUME)
+            .map(|i| if i % 2 == 0 { BlockType::STONE } else { BlockType::DIRT })
+            .collect::<Vec<_>>());
+        assert!(big.memory_size() > cache.budget);
+        cache.insert(pos(1), big);
+        assert_eq!(cached(&cache), [pos(0)]);
+        assert_eq!(cache.used, chunk().memory_size());
+    }
+}
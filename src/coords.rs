@@ -0,0 +1,213 @@
+use crate::chunk::CHUNK_SIZE;
+use nalgebra::{Vector3, vector};
+use std::ops::Add;
+
+// Position of a block in the world. Blocks are centered on integer coordinates, so block
+// (0, 0, 0) covers -0.5..0.5 on every axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WorldPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+// Position of a chunk, in chunks. Chunk (0, 0, 0) holds blocks (0, 0, 0) to
+// (CHUNK_SIZE-1, CHUNK_SIZE-1, CHUNK_SIZE-1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+// Position of a block within its chunk, every axis is in 0..CHUNK_SIZE
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LocalPos {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+}
+
+impl WorldPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    // the block a point in world space is inside of, points on a boundary between two blocks
+    // belong to the one in the positive direction
+    pub fn containing(point: Vector3<f32>) -> Self {
+        Self {
+            x: (point.x + 0.5).floor() as i32,
+            y: (point.y + 0.5).floor() as i32,
+            z: (point.z + 0.5).floor() as i32,
+        }
+    }
+
+    pub fn center(self) -> Vector3<f32> {
+        vector![self.x as f32, self.y as f32, self.z as f32]
+    }
+
+    pub fn chunk(self) -> ChunkPos {
+        let size = CHUNK_SIZE as i32;
+        ChunkPos {
+            x: self.x.div_euclid(size),
+            y: self.y.div_euclid(size),
+            z: self.z.div_euclid(size),
+        }
+    }
+
+    pub fn local(self) -> LocalPos {
+        let size = CHUNK_SIZE as i32;
+        LocalPos {
+            x: self.x.rem_euclid(size) as usize,
+            y: self.y.rem_euclid(size) as usize,
+            z: self.z.rem_euclid(size) as usize,
+        }
+    }
+
+    pub fn split(self) -> (ChunkPos, LocalPos) {
+        (self.chunk(), self.local())
+    }
+}
+
+impl Add<Vector3<i32>> for WorldPos {
+    type Output = Self;
+
+    fn add(self, offset: Vector3<i32>) -> Self {
+        Self::new(self.x + offset.x, self.y + offset.y, self.z + offset.z)
+    }
+}
+
+impl ChunkPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    // the chunk holding the block a point in world space is inside of
+    pub fn containing(point: Vector3<f32>) -> Self {
+        WorldPos::containing(point).chunk()
+    }
+
+    pub fn world_pos(self, local: LocalPos) -> WorldPos {
+        let size = CHUNK_SIZE as i32;
+        WorldPos {
+            x: self.x * size + local.x as i32,
+            y: self.y * size + local.y as i32,
+            z: self.z * size + local.z as i32,
+        }
+    }
+
+    pub fn origin(self) -> WorldPos {
+        self.world_pos(LocalPos::new(0, 0, 0))
+    }
+
+    // distance along the axis the two chunks are furthest apart on
+    pub fn max_distance(self, other: ChunkPos) -> i32 {
+        (self.x - other.x).abs()
+            .max((self.y - other.y).abs())
+            .max((self.z - other.z).abs())
+    }
+
+    pub fn vector(self) -> Vector3<i32> {
+        vector![self.x, self.y, self.z]
+    }
+}
+
+impl Add<Vector3<i32>> for ChunkPos {
+    type Output = Self;
+
+    fn add(self, offset: Vector3<i32>) -> Self {
+        Self::new(self.x + offset.x, self.y + offset.y, self.z + offset.z)
+    }
+}
+
+impl LocalPos {
+    pub fn new(x: usize, y: usize, z: usize) -> Self {
+        debug_assert!(x < CHUNK_SIZE && y < CHUNK_SIZE && z < CHUNK_SIZE);
+        Self { x, y, z }
+    }
+
+    // inverse of index, blocks are stored x fastest, then y, then z
+    pub fn from_index(i: usize) -> Self {
+        Self::new(i % CHUNK_SIZE, (i / CHUNK_SIZE) % CHUNK_SIZE, i / (CHUNK_SIZE*CHUNK_SIZE))
+    }
+
+    pub fn index(self) -> usize {
+        self.x + CHUNK_SIZE*self.y + CHUNK_SIZE*CHUNK_SIZE*self.z
+    }
+
+    pub fn vector(self) -> Vector3<i32> {
+        vector![self.x as i32, self.y as i32, self.z as i32]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const SIZE: i32 = CHUNK_SIZE as i32;
+
+    fn world_pos() -> impl Strategy<Value = WorldPos> {
+        (-100_000..100_000, -100_000..100_000, -100_000..100_000)
+            .prop_map(|(x, y, z)| WorldPos::new(x, y, z))
+    }
+
+    proptest! {
+        #[test]
+        fn split_round_trips(pos in world_pos()) {
+            let (chunk, local) = pos.split();
+            prop_assert_eq!(chunk.world_pos(local), pos);
+        }
+
+        #[test]
+        fn local_in_range(pos in world_pos()) {
+            let local = pos.local();
+            prop_assert!(local.x < CHUNK_SIZE && local.y < CHUNK_SIZE && local.z < CHUNK_SIZE);
+            prop_assert_eq!(LocalPos::from_index(local.index()), local);
+        }
+
+        #[test]
+        fn chunk_edges(chunk in -3000..3000i32, offset in 0..SIZE) {
+            let last = WorldPos::new(chunk * SIZE - 1, 0, 0);
+            let first = WorldPos::new(chunk * SIZE, 0, 0);
+            prop_assert_eq!(last.chunk().x, chunk - 1);
+            prop_assert_eq!(last.local().x, CHUNK_SIZE - 1);
+            prop_assert_eq!(first.chunk().x, chunk);
+            prop_assert_eq!(first.local().x, 0);
+            prop_assert_eq!(WorldPos::new(chunk * SIZE + offset, 0, 0).chunk().x, chunk);
+        }
+
+        #[test]
+        fn containing_block_holds_point(x in -10_000.0..10_000.0f32, y in -10_000.0..10_000.0f32, z in -10_000.0..10_000.0f32) {
+            let point = vector![x, y, z];
+            let center = WorldPos::containing(point).center();
+            for axis in 0..3 {
+                prop_assert!(point[axis] >= center[axis] - 0.5);
+                prop_assert!(point[axis] < center[axis] + 0.5);
+            }
+        }
+
+        #[test]
+        fn chunk_containing_matches_block(x in -10_000.0..10_000.0f32, y in -10_000.0..10_000.0f32, z in -10_000.0..10_000.0f32) {
+            let point = vector![x, y, z];
+            let origin = ChunkPos::containing(point).origin().center();
+            for axis in 0..3 {
+                prop_assert!(point[axis] >= origin[axis] - 0.5);
+                prop_assert!(point[axis] < origin[axis] - 0.5 + CHUNK_SIZE as f32);
+            }
+        }
+    }
+
+    #[test]
+    fn negative_boundaries() {
+        assert_eq!(WorldPos::containing(vector![-0.5, 0.0, 0.0]).x, 0);
+        assert_eq!(WorldPos::containing(vector![-0.51, 0.0, 0.0]).x, -1);
+        assert_eq!(WorldPos::containing(vector![-1.0, -1.4, -1.6]), WorldPos::new(-1, -1, -2));
+        assert_eq!(WorldPos::new(-1, -SIZE, -SIZE - 1).split(), (
+            ChunkPos::new(-1, -1, -2),
+            LocalPos::new(CHUNK_SIZE - 1, 0, CHUNK_SIZE - 1),
+        ));
+        assert_eq!(ChunkPos::containing(vector![-0.6, SIZE as f32 - 0.5, 0.0]), ChunkPos::new(-1, 1, 0));
+    }
+}
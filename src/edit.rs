@@ -0,0 +1,346 @@
+use crate::block::{BlockType, BlockState};
+use crate::coords::WorldPos;
+use crate::terrain::{Terrain, TerrainEdit};
+use nalgebra::{Vector3, vector};
+
+// Axis aligned box of blocks, min and max are both inside it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cuboid {
+    pub min: WorldPos,
+    pub max: WorldPos,
+}
+
+impl Cuboid {
+    // box between any two opposite corners
+    pub fn new(a: WorldPos, b: WorldPos) -> Self {
+        Self {
+            min: WorldPos::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: WorldPos::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    pub fn size(&self) -> Vector3<usize> {
+        vector![
+            (self.max.x - self.min.x + 1) as usize,
+            (self.max.y - self.min.y + 1) as usize,
+            (self.max.z - self.min.z + 1) as usize,
+        ]
+    }
+
+    fn on_border(&self, pos: WorldPos) -> bool {
+        pos.x == self.min.x || pos.x == self.max.x
+            || pos.y == self.min.y || pos.y == self.max.y
+            || pos.z == self.min.z || pos.z == self.max.z
+    }
+
+    // x fastest, then y, then z, the same order as a Clipboard's blocks
+    pub fn iter(&self) -> impl Iterator<Item = WorldPos> {
+        let (min, max) = (self.min, self.max);
+        (min.z..=max.z).flat_map(move |z| {
+            (min.y..=max.y).flat_map(move |y| {
+                (min.x..=max.x).map(move |x| WorldPos::new(x, y, z))
+            })
+        })
+    }
+}
+
+// Blocks copied out of the terrain, to be pasted somewhere else
+#[derive(Debug, Clone)]
+pub struct Clipboard {
+    size: Vector3<usize>,
+    blocks: Vec<(BlockType, BlockState)>,
+}
+
+impl Clipboard {
+    pub fn new(size: Vector3<usize>) -> Self {
+        Self {
+            size,
+            blocks: vec![(BlockType::AIR, BlockState::default()); size.x * size.y * size.z],
+        }
+    }
+
+    // blocks in unloaded chunks are copied as air
+    pub fn copy(terrain: &Terrain, region: Cuboid) -> Self {
+        Self {
+            size: region.size(),
+            blocks: region.iter()
+                .map(|pos| match terrain.get_block(pos) {
+                    Some((_, block, state)) => (block, state),
+                    None => (BlockType::AIR, BlockState::default()),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn size(&self) -> Vector3<usize> {
+        self.size
+    }
+
+    fn index(&self, pos: Vector3<usize>) -> usize {
+        pos.x + self.size.x*pos.y + self.size.x*self.size.y*pos.z
+    }
+
+    pub fn get(&self, pos: Vector3<usize>) -> (BlockType, BlockState) {
+        self.blocks[self.index(pos)]
+    }
+
+    pub fn set(&mut self, pos: Vector3<usize>, block: BlockType, state: BlockState) {
+        let i = self.index(pos);
+        self.blocks[i] = (block, state);
+    }
+
+    fn positions(&self) -> impl Iterator<Item = Vector3<usize>> {
+        let size = self.size;
+        (0..size.z).flat_map(move |z| {
+            (0..size.y).flat_map(move |y| (0..size.x).map(move |x| vector![x, y, z]))
+        })
+    }
+
+    // builds a clipboard of the given size where each block comes from one in self
+    fn remap(&self, size: Vector3<usize>, source: impl Fn(Vector3<usize>) -> Vector3<usize>, state: impl Fn(BlockState) -> BlockState) -> Self {
+        let mut out = Self::new(size);
+        for pos in out.positions() {
+            let (block, old_state) = self.get(source(pos));
+            let new_state = if block.oriented() { state(old_state) } else { old_state };
+            out.set(pos, block, new_state);
+        }
+        out
+    }
+
+    // quarter turns around the y axis, +x turning towards +z
+    pub fn rotated(&self, turns: u32) -> Self {
+        let mut out = self.clone();
+        for _ in 0..turns % 4 {
+            // (x, y, z) ends up at (size.z-1 - z, y, x)
+            let size = out.size;
+            out = out.remap(
+                vector![size.z, size.y, size.x],
+                |p| vector![p.z, p.y, size.z - 1 - p.x],
+                |s| s.with_orientation(s.orientation().rotated_y()),
+            );
+        }
+        out
+    }
+
+    // flipped along axis, 0 for x, 1 for y, 2 for z
+    pub fn mirrored(&self, axis: usize) -> Self {
+        let size = self.size;
+        self.remap(
+            size,
+            |mut p| {
+                p[axis] = size[axis] - 1 - p[axis];
+                p
+            },
+            |s| s.with_orientation(s.orientation().mirrored(axis)),
+        )
+    }
+}
+
+// Bulk edits. Like any other edit they are batched into the next TerrainChanges, so every chunk
+// they touch is remeshed once no matter how many of its blocks changed. Blocks in unloaded chunks
+// are skipped, each operation returns how many blocks it set.
+impl TerrainEdit<'_> {
+    pub fn fill(&mut self, region: Cuboid, block: BlockType) -> usize {
+        let mut count = 0;
+        for pos in region.iter() {
+            if self.set_block(pos, block) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    // walls of block around an empty inside
+    pub fn hollow(&mut self, region: Cuboid, block: BlockType) -> usize {
+        let mut count = 0;
+        for pos in region.iter() {
+            let fill = if region.on_border(pos) { block } else { BlockType::AIR };
+            if self.set_block(pos, fill) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    pub fn replace(&mut self, region: Cuboid, from: BlockType, to: BlockType) -> usize {
+        let mut count = 0;
+        for pos in region.iter() {
+            if let Some((_, block, _)) = self.get_block(pos) {
+                if block == from && self.set_block(pos, to) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    // every block whose center is within radius of center's, carve with BlockType::AIR
+    pub fn sphere(&mut self, center: WorldPos, radius: f32, block: BlockType) -> usize {
+        let r = radius.ceil() as i32;
+        let region = Cuboid::new(center + vector![-r, -r, -r], center + vector![r, r, r]);
+        let mut count = 0;
+        for pos in region.iter() {
+            if (pos.center() - center.center()).norm_squared() <= radius*radius && self.set_block(pos, block) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    // upright cylinder standing on base, height blocks tall
+    pub fn cylinder(&mut self, base: WorldPos, radius: f32, height: u32, block: BlockType) -> usize {
+        if height == 0 {
+            return 0;
+        }
+        let r = radius.ceil() as i32;
+        let region = Cuboid::new(base + vector![-r, 0, -r], base + vector![r, height as i32 - 1, r]);
+        let mut count = 0;
+        for pos in region.iter() {
+            let (dx, dz) = ((pos.x - base.x) as f32, (pos.z - base.z) as f32);
+            if dx*dx + dz*dz <= radius*radius && self.set_block(pos, block) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    // places the clipboard like paste, but its air leaves the terrain alone, for structures
+    pub fn place(&mut self, clipboard: &Clipboard, origin: WorldPos) -> usize {
+        let mut count = 0;
+        for pos in clipboard.positions() {
+            let (block, state) = clipboard.get(pos);
+            if block != BlockType::AIR && self.set_block_state(origin + pos.cast::<i32>(), block, state) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    // places the clipboard with its lowest corner at origin, air included
+    pub fn paste(&mut self, clipboard: &Clipboard, origin: WorldPos) -> usize {
+        let mut count = 0;
+        for pos in clipboard.positions() {
+            let (block, state) = clipboard.get(pos);
+            if self.set_block_state(origin + pos.cast::<i32>(), block, state) {
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockFace;
+    use crate::coords::ChunkPos;
+    use crate::worldgen::FlatGenerator;
+    use std::{collections::HashSet, sync::Arc};
+
+    // stone up to y = 31, air in the chunks above
+    fn terrain() -> Terrain {
+        let mut chunks = Vec::new();
+        for x in -1..=1 {
+            for y in 0..=1 {
+                for z in -1..=1 {
+                    chunks.push(ChunkPos::new(x, y, z));
+                }
+            }
+        }
+        Terrain::with_chunks(Arc::new(FlatGenerator::new(vec![(BlockType::STONE, 32)])), chunks)
+    }
+
+    // stone with a different state everywhere, so any mixed up index shows. Stone isn't
+    // oriented, so its states are copied as they are.
+    fn numbered(size: Vector3<usize>) -> Clipboard {
+        let mut clipboard = Clipboard::new(size);
+        for (i, pos) in clipboard.positions().collect::<Vec<_>>().into_iter().enumerate() {
+            clipboard.set(pos, BlockType::STONE, BlockState(i as u16 + 1));
+        }
+        clipboard
+    }
+
+    fn same(a: &Clipboard, b: &Clipboard) -> bool {
+        a.size() == b.size() && a.positions().all(|pos| a.get(pos) == b.get(pos))
+    }
+
+    #[test]
+    fn rotation_turns_around_y() {
+        let clipboard = numbered(vector![2, 1, 3]);
+        let turned = clipboard.rotated(1);
+        assert_eq!(turned.size(), vector![3, 1, 2]);
+        // (x, y, z) ends up at (size.z-1 - z, y, x)
+        for pos in clipboard.positions() {
+            assert_eq!(turned.get(vector![2 - pos.z, pos.y, pos.x]), clipboard.get(pos));
+        }
+        assert!(same(&clipboard.rotated(4), &clipboard));
+        assert!(same(&turned.rotated(3), &clipboard));
+        assert!(same(&clipboard.rotated(2).rotated(2), &clipboard));
+    }
+
+    #[test]
+    fn mirroring_flips_one_axis() {
+        let clipboard = numbered(vector![2, 3, 4]);
+        for axis in 0..3 {
+            let flipped = clipboard.mirrored(axis);
+            assert_eq!(flipped.size(), clipboard.size());
+            let mut far = vector![0, 0, 0];
+            far[axis] = clipboard.size()[axis] - 1;
+            assert_eq!(flipped.get(far), clipboard.get(vector![0, 0, 0]));
+            assert!(same(&flipped.mirrored(axis), &clipboard));
+        }
+    }
+
+    #[test]
+    fn oriented_blocks_turn_with_the_clipboard() {
+        let mut clipboard = Clipboard::new(vector![1, 1, 1]);
+        let state = BlockState::default().with_orientation(BlockFace::Right);
+        clipboard.set(vector![0, 0, 0], BlockType::WOOD, state);
+        assert_eq!(clipboard.rotated(1).get(vector![0, 0, 0]).1.orientation(), BlockFace::Front);
+        assert_eq!(clipboard.mirrored(0).get(vector![0, 0, 0]).1.orientation(), BlockFace::Left);
+        assert_eq!(clipboard.mirrored(2).get(vector![0, 0, 0]).1.orientation(), BlockFace::Right);
+    }
+
+    #[test]
+    fn sphere_and_cylinder_cover_blocks_within_radius() {
+        let mut terrain = terrain();
+        let center = WorldPos::new(0, 48, 0);
+        // the center, its 6 face neighbors and 12 edge neighbors, but not the 8 corners
+        assert_eq!(terrain.edit(|e| e.sphere(center, 1.5, BlockType::SAND)), 19);
+        assert_eq!(terrain.get_block(center + vector![1, 1, 0]).unwrap().1, BlockType::SAND);
+        assert_eq!(terrain.get_block(center + vector![1, 1, 1]).unwrap().1, BlockType::AIR);
+        assert_eq!(terrain.edit(|e| e.sphere(center, 0.0, BlockType::DIRT)), 1);
+
+        // a plus shape 3 layers high, starting at the base
+        let base = WorldPos::new(8, 40, 8);
+        assert_eq!(terrain.edit(|e| e.cylinder(base, 1.0, 3, BlockType::WOOD)), 15);
+        assert_eq!(terrain.get_block(base + vector![0, 2, -1]).unwrap().1, BlockType::WOOD);
+        assert_eq!(terrain.get_block(base + vector![0, 3, 0]).unwrap().1, BlockType::AIR);
+        assert_eq!(terrain.get_block(base + vector![1, 0, 1]).unwrap().1, BlockType::AIR);
+        assert_eq!(terrain.edit(|e| e.cylinder(base, 1.0, 0, BlockType::WOOD)), 0);
+    }
+
+    #[test]
+    fn paste_across_chunk_borders() {
+        let mut terrain = terrain();
+        terrain.take_changes();
+        let clipboard = numbered(vector![4, 1, 1]);
+        // two blocks on each side of the border between chunks x 0 and 1
+        let origin = WorldPos::new(30, 40, 5);
+        assert_eq!(terrain.edit(|e| e.paste(&clipboard, origin)), 4);
+        for (i, x) in (30..34).enumerate() {
+            let (_, block, state) = terrain.get_block(WorldPos::new(x, 40, 5)).unwrap();
+            assert_eq!((block, state), (BlockType::STONE, BlockState(i as u16 + 1)));
+        }
+
+        let changes = terrain.take_changes();
+        let modified: HashSet<_> = changes.modified_chunks.keys().copied().collect();
+        assert_eq!(modified, HashSet::from([ChunkPos::new(0, 1, 0), ChunkPos::new(1, 1, 0)]));
+        assert!(changes.modified_chunks.values().all(|blocks| blocks.len() == 2));
+        assert!(changes.remesh_chunks.contains(&ChunkPos::new(1, 1, 0)));
+        assert!(changes.remesh_chunks.contains(&ChunkPos::new(0, 1, 0)));
+
+        // chunk x 2 isn't loaded, those blocks are skipped
+        assert_eq!(terrain.edit(|e| e.paste(&clipboard, WorldPos::new(62, 40, 5))), 2);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::input::InputState;
+use crate::terrain::Terrain;
+use crate::coords::WorldPos;
+use crate::block::BlockType;
+use crate::edit::{Clipboard, Cuboid};
+use crate::vox;
+use winit::event::VirtualKeyCode;
+use nalgebra::{Vector3, vector};
+use std::collections::HashSet;
+
+const PICK_DEPTH: i32 = 8; // how far below the player blocks get picked
+// MagicaVoxel models going out of and into the game
+const EXPORT_PATH: &str = "world/export.vox";
+const IMPORT_PATH: &str = "world/import.vox";
+
+// keys the editor reacts to, once per press
+const KEYS: [VirtualKeyCode; 15] = [
+    VirtualKeyCode::F1, VirtualKeyCode::F2, VirtualKeyCode::Q,
+    VirtualKeyCode::F, VirtualKeyCode::H, VirtualKeyCode::Z, VirtualKeyCode::B, VirtualKeyCode::N,
+    VirtualKeyCode::C, VirtualKeyCode::V, VirtualKeyCode::P, VirtualKeyCode::R, VirtualKeyCode::M,
+    VirtualKeyCode::F5, VirtualKeyCode::F6,
+];
+
+// Bulk editing for building levels, around the block the player is in:
+//   F1, F2  mark the corners of the selection
+//   Q       picks the block to edit with, the first one below the player, air over nothing
+//   F, H    fill the selection, or only its walls, clearing the inside
+//   Z       replaces the block picked before the last one with the last one in the selection
+//   B, N    a sphere around the first corner reaching the second, or a cylinder around the
+//           first as wide and tall as the selection reaches
+//   C       copies the selection to the clipboard
+//   V, P    paste it with its lowest corner at the player, P keeps what is where it has air
+//   R, M    turn the clipboard a quarter around y, or mirror it along x
+//   F5      exports the selection to EXPORT_PATH
+//   F6      loads the model at IMPORT_PATH into the clipboard, P places it as a structure
+pub struct Editor {
+    corners: [Option<WorldPos>; 2],
+    block: BlockType,
+    previous_block: BlockType,
+    clipboard: Option<Clipboard>,
+    held: HashSet<VirtualKeyCode>,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self {
+            corners: [None; 2],
+            block: BlockType::AIR,
+            previous_block: BlockType::AIR,
+            clipboard: None,
+            held: HashSet::new(),
+        }
+    }
+
+    pub fn update(&mut self, input: &InputState, terrain: &mut Terrain, position: Vector3<f32>) {
+        let pressed: Vec<_> = KEYS.into_iter().filter(|key| self.pressed(input, *key)).collect();
+        let here = WorldPos::containing(position);
+        for key in pressed {
+            self.run(key, terrain, here);
+        }
+    }
+
+    // true on the update a key goes down
+    fn pressed(&mut self, input: &InputState, key: VirtualKeyCode) -> bool {
+        if !input.key_pressed(key) {
+            self.held.remove(&key);
+            return false;
+        }
+        self.held.insert(key)
+    }
+
+    fn run(&mut self, key: VirtualKeyCode, terrain: &mut Terrain, here: WorldPos) {
+        let block = self.block;
+        match key {
+            VirtualKeyCode::F1 => self.corners[0] = Some(here),
+            VirtualKeyCode::F2 => self.corners[1] = Some(here),
+            VirtualKeyCode::Q => {
+                self.previous_block = self.block;
+                self.block = (1..=PICK_DEPTH)
+                    .filter_map(|depth| terrain.get_block(here + vector![0, -depth, 0]))
+                    .map(|(_, block, _)| block)
+                    .find(|block| *block != BlockType::AIR)
+                    .unwrap_or(BlockType::AIR);
+                log::info!("editing with {}", self.block.name());
+            },
+            VirtualKeyCode::C => if let Some(selection) = self.selection() {
+                self.clipboard = Some(Clipboard::copy(terrain, selection));
+            },
+            VirtualKeyCode::V => if let Some(clipboard) = &self.clipboard {
+                terrain.edit(|e| e.paste(clipboard, here));
+            },
+            VirtualKeyCode::P => if let Some(clipboard) = &self.clipboard {
+                terrain.edit(|e| e.place(clipboard, here));
+            },
+            VirtualKeyCode::F5 => if let Some(selection) = self.selection() {
+                if let Err(e) = vox::export(terrain, selection, EXPORT_PATH) {
+                    eprintln!("failed to export the selection: {:?}", e);
+                }
+            },
+            VirtualKeyCode::F6 => match vox::load(IMPORT_PATH) {
+                Ok(clipboard) => self.clipboard = Some(clipboard),
+                Err(e) => eprintln!("failed to import a model: {:?}", e),
+            },
+            VirtualKeyCode::R => self.clipboard = self.clipboard.as_ref().map(|clipboard| clipboard.rotated(1)),
+            VirtualKeyCode::M => self.clipboard = self.clipboard.as_ref().map(|clipboard| clipboard.mirrored(0)),
+            _ => {
+                let (selection, [a, b]) = match (self.selection(), self.corners) {
+                    (Some(selection), [Some(a), Some(b)]) => (selection, [a, b]),
+                    _ => return,
+                };
+                let offset = (b.center() - a.center()).map(f32::abs);
+                let count = terrain.edit(|e| match key {
+                    VirtualKeyCode::F => e.fill(selection, block),
+                    VirtualKeyCode::H => e.hollow(selection, block),
+                    VirtualKeyCode::Z => e.replace(selection, self.previous_block, block),
+                    VirtualKeyCode::B => e.sphere(a, offset.norm(), block),
+                    VirtualKeyCode::N => {
+                        let base = WorldPos::new(a.x, selection.min.y, a.z);
+                        e.cylinder(base, offset.xz().norm(), offset.y as u32 + 1, block)
+                    },
+                    _ => 0,
+                });
+                log::info!("set {} blocks", count);
+            },
+        }
+    }
+
+    fn selection(&self) -> Option<Cuboid> {
+        match self.corners {
+            [Some(a), Some(b)] => Some(Cuboid::new(a, b)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::event::ElementState;
+
+    // presses and releases key with the player's eyes in the block at pos
+    fn press(editor: &mut Editor, terrain: &mut Terrain, key: VirtualKeyCode, pos: WorldPos) {
+        let mut input = InputState::new();
+        input.update_key(key, ElementState::Pressed);
+        editor.update(&input, terrain, pos.center());
+        // held down, it doesn't run again
+        editor.update(&input, terrain, pos.center());
+        input.update_key(key, ElementState::Released);
+        editor.update(&input, terrain, pos.center());
+    }
+
+    #[test]
+    fn fill_and_copy_the_selection() {
+        let mut terrain = Terrain::flat(32);
+        let mut editor = Editor::new();
+        press(&mut editor, &mut terrain, VirtualKeyCode::Q, WorldPos::new(5, 34, 5));
+        press(&mut editor, &mut terrain, VirtualKeyCode::F1, WorldPos::new(5, 32, 5));
+        press(&mut editor, &mut terrain, VirtualKeyCode::F2, WorldPos::new(6, 33, 7));
+        press(&mut editor, &mut terrain, VirtualKeyCode::F, WorldPos::new(5, 34, 5));
+        let stone = |terrain: &Terrain, pos| terrain.get_block(pos).unwrap().1 == BlockType::STONE;
+        let filled = Cuboid::new(WorldPos::new(5, 32, 5), WorldPos::new(6, 33, 7));
+        assert!(filled.iter().all(|pos| stone(&terrain, pos)));
+        assert!(!stone(&terrain, WorldPos::new(5, 34, 5)));
+
+        press(&mut editor, &mut terrain, VirtualKeyCode::C, WorldPos::new(5, 34, 5));
+        press(&mut editor, &mut terrain, VirtualKeyCode::V, WorldPos::new(20, 40, 20));
+        let pasted = Cuboid::new(WorldPos::new(20, 40, 20), WorldPos::new(21, 41, 22));
+        assert!(pasted.iter().all(|pos| stone(&terrain, pos)));
+        assert!(!stone(&terrain, WorldPos::new(22, 40, 20)));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::terrain::TerrainEdit;
+use crate::block::{self, BlockType, BlockFace, BlockState};
+use crate::coords::WorldPos;
+use crate::mesh::{CMesh, MeshVertex};
+use crate::model::BlockModel;
+use crate::tick::TICK_LENGTH;
+use nalgebra::{Vector3, vector};
+
+pub const FALL_DELAY: u64 = 2; // ticks between losing support and falling
+const GRAVITY: f32 = 30.0; // blocks per second squared
+const MAX_FALL_SPEED: f32 = 40.0;
+
+// A block that lost its support, falling until it lands and turns back into a block
+pub struct FallingBlock {
+    pub block: BlockType,
+    pub state: BlockState,
+    pub position: Vector3<f32>, // center
+    pub previous: Vector3<f32>, // position at the last tick, for drawing in between ticks
+    velocity: f32, // downwards
+}
+
+impl FallingBlock {
+    pub fn new(block: BlockType, state: BlockState, block_world_pos: WorldPos) -> Self {
+        let position = block_world_pos.center();
+        Self {
+            block,
+            state,
+            position,
+            previous: position,
+            velocity: 0.0,
+        }
+    }
+
+    // moves the block one tick down, returning where it landed if it did. Falling blocks wait in
+    // the air while the chunk below them isn't loaded.
+    pub fn step(&mut self, edit: &TerrainEdit) -> Option<WorldPos> {
+        self.previous = self.position;
+        self.velocity = (self.velocity + GRAVITY * TICK_LENGTH.as_secs_f32()).min(MAX_FALL_SPEED);
+        let target = self.position.y - self.velocity * TICK_LENGTH.as_secs_f32();
+
+        // every block passed through is checked, so fast blocks don't tunnel
+        let mut below = WorldPos::containing(self.position) + vector![0, -1, 0];
+        while below.y as f32 + 0.5 > target - 0.5 {
+            match edit.get_block(below) {
+                None => {
+                    self.velocity = 0.0;
+                    return None;
+                },
+                Some((_, block, _)) if block::registry().get(block).collision => {
+                    return Some(below + vector![0, 1, 0]);
+                },
+                _ => below = below + vector![0, -1, 0],
+            }
+        }
+        self.position.y = target;
+        None
+    }
+
+    // turns back into a block at pos. Whatever took its place in the meantime breaks its fall, it
+    // lands on top of that. Returns where it stopped if that chunk isn't loaded.
+    pub fn land(&self, edit: &mut TerrainEdit, mut pos: WorldPos) -> Option<WorldPos> {
+        loop {
+            match edit.get_block(pos) {
+                Some((_, block, _)) if !block::registry().get(block).collision => {
+                    edit.set_block_state(pos, self.block, self.state);
+                    return None;
+                },
+                Some(_) => pos = pos + BlockFace::Top.normal(),
+                None => return Some(pos),
+            }
+        }
+    }
+}
+
+fn supported(edit: &TerrainEdit, block_world_pos: WorldPos) -> bool {
+    match edit.get_block(block_world_pos + BlockFace::Bottom.normal()) {
+        Some((_, block, _)) => block::registry().get(block).collision,
+        // can't tell, the chunk below isn't loaded
+        None => true,
+    }
+}
+
+// scheduled behavior of blocks that fall. Everything that falls stacked on top goes with it,
+// so whole columns come down together.
+pub fn fall(edit: &mut TerrainEdit, block_world_pos: WorldPos) {
+    if supported(edit, block_world_pos) {
+        return;
+    }
+    let mut column = block_world_pos;
+    while let Some((_, block, _)) = edit.get_block(column) {
+        if !block::registry().get(block).falls {
+            break;
+        }
+        edit.start_falling(column);
+        column = column + BlockFace::Top.normal();
+    }
+}
+
+// one mesh for all falling blocks, drawn at their position blend between the last two ticks
+pub fn mesh<'a>(falling_blocks: impl Iterator<Item = &'a FallingBlock>, blend: f32) -> Option<CMesh> {
+    let registry = block::registry();
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for falling_block in falling_blocks {
+        let properties = registry.get(falling_block.block);
+        let center = falling_block.previous.lerp(&falling_block.position, blend);
+        for quad in properties.model.quads(falling_block.state, |_| false) {
+            let layer = if properties.model == BlockModel::Cube {
+                properties.texture(&quad.texture_face, falling_block.state)
+            } else {
+                properties.textures[quad.texture_face as usize]
+            };
+            let o = vertices.len() as u32;
+            vertices.extend(quad.positions.iter().zip(quad.tex_coords).map(|(p, tex_coords)| MeshVertex {
+                position: (center + p).into(),
+                tex_coords,
+                normal: quad.normal,
+                ao: 0.0,
+                layer,
+            }));
+            indices.extend_from_slice(&[o,o+2,o+1,o+2,o+3,o+1]);
+        }
+    }
+    if indices.is_empty() {
+        None
+    } else {
+        Some(CMesh::new(&vertices, &indices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::Terrain;
+
+    #[test]
+    fn columns_land_stacked_in_order() {
+        let mut terrain = Terrain::flat(32);
+        let support = WorldPos::new(10, 40, 10);
+        terrain.set_block(support, BlockType::STONE);
+        // each block told apart by its state
+        for i in 1..=5 {
+            let block = if i % 2 == 0 { BlockType::SAND } else { block::registry().by_name("gravel").unwrap() };
+            terrain.set_block_state(support + vector![0, i, 0], block, BlockState(i as u16));
+        }
+        let column: Vec<_> = (1..=5).map(|i| terrain.get_block(support + vector![0, i, 0]).unwrap()).collect();
+
+        terrain.set_block(support, BlockType::AIR);
+        terrain.run_ticks(FALL_DELAY + 40);
+        for (i, (_, block, state)) in column.into_iter().enumerate() {
+            let (_, landed, landed_state) = terrain.get_block(WorldPos::new(10, 32 + i as i32, 10)).unwrap();
+            assert_eq!((landed, landed_state), (block, state));
+        }
+        for y in 37..=45 {
+            assert_eq!(terrain.get_block(WorldPos::new(10, y, 10)).unwrap().1, BlockType::AIR);
+        }
+    }
+
+    #[test]
+    fn falls_are_checked_through_every_block_passed() {
+        let mut terrain = Terrain::flat(32);
+        let top = WorldPos::new(10, 62, 10);
+        terrain.set_block(WorldPos::new(10, 40, 10), BlockType::STONE);
+        terrain.set_block(top, BlockType::SAND);
+        terrain.edit(|e| e.start_falling(top));
+        // over a block a tick by the time it gets there, but it doesn't pass through the stone
+        terrain.run_ticks(40);
+        assert_eq!(terrain.get_block(WorldPos::new(10, 41, 10)).unwrap().1, BlockType::SAND);
+    }
+
+    #[test]
+    fn blocks_land_on_top_of_what_filled_their_landing_spot() {
+        let mut terrain = Terrain::flat(32);
+        let landing = WorldPos::new(10, 32, 10);
+        terrain.set_block(landing, BlockType::SAND);
+        terrain.edit(|e| e.start_falling(landing));
+        terrain.set_block(landing, BlockType::STONE);
+        terrain.run_ticks(1);
+        assert_eq!(terrain.get_block(landing).unwrap().1, BlockType::STONE);
+        assert_eq!(terrain.get_block(landing + vector![0, 1, 0]).unwrap().1, BlockType::SAND);
+    }
+
+    #[test]
+    fn blocks_still_falling_are_saved_where_they_are() {
+        let mut terrain = Terrain::flat(32);
+        let top = WorldPos::new(10, 60, 10);
+        terrain.set_block(top, BlockType::SAND);
+        terrain.edit(|e| e.start_falling(top));
+        terrain.run_ticks(5);
+        let falling = WorldPos::containing(terrain.falling_blocks().next().unwrap().position);
+        assert!(falling.y > 32 && falling.y < 60);
+        // what is in the way puts it on top
+        terrain.set_block(falling, BlockType::STONE);
+        terrain.save();
+        assert_eq!(terrain.falling_blocks().count(), 0);
+        assert_eq!(terrain.get_block(falling + vector![0, 1, 0]).unwrap().1, BlockType::SAND);
+        assert_eq!(terrain.get_block(WorldPos::new(10, 32, 10)).unwrap().1, BlockType::AIR);
+    }
+
+    #[test]
+    fn supported_blocks_stay() {
+        let mut terrain = Terrain::flat(32);
+        let pos = WorldPos::new(10, 32, 10);
+        terrain.set_block(pos, BlockType::SAND);
+        terrain.edit(|e| fall(e, pos));
+        terrain.run_ticks(FALL_DELAY * 2);
+        assert_eq!(terrain.get_block(pos).unwrap().1, BlockType::SAND);
+    }
+}
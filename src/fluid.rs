@@ -0,0 +1,162 @@
+use crate::terrain::TerrainEdit;
+use crate::block::{self, BlockType, BlockFace, BlockState};
+use crate::coords::WorldPos;
+
+// Fluid blocks with level 0 are sources, flowing blocks count up from there and dry up past
+// MAX_LEVEL. Falling fluid, with more of itself above, is level 1.
+pub const MAX_LEVEL: u8 = 7;
+pub const FLOW_DELAY: u64 = 5; // ticks before a fluid reacts to a change next to it
+
+const HORIZONTAL: [BlockFace; 4] = [BlockFace::Front, BlockFace::Back, BlockFace::Left, BlockFace::Right];
+
+// height of the surface above the bottom of the block, full for sources and falling fluid
+pub fn surface_height(level: u8, covered: bool) -> f32 {
+    if level == 0 || covered {
+        1.0
+    } else {
+        (MAX_LEVEL + 1 - level) as f32 / (MAX_LEVEL + 1) as f32
+    }
+}
+
+// fluids wash away blocks without collision, like plants, but don't mix
+fn replaceable(block: BlockType) -> bool {
+    let properties = block::registry().get(block);
+    !properties.collision && !properties.fluid
+}
+
+// scheduled behavior of fluid blocks. A flowing block first takes the level its neighbors feed it,
+// then the fluid falls if it can and otherwise spreads sideways one level further.
+pub fn flow(edit: &mut TerrainEdit, block_world_pos: WorldPos) {
+    let (fluid, state) = match edit.get_block(block_world_pos) {
+        Some((_, block, state)) => (block, state),
+        None => return,
+    };
+    let level = state.level();
+
+    if level > 0 {
+        let falling = edit.get_block(block_world_pos + BlockFace::Top.normal())
+            .is_some_and(|(_, block, _)| block == fluid);
+        let fed = if falling {
+            Some(1)
+        } else {
+            HORIZONTAL.iter()
+                .filter_map(|face| match edit.get_block(block_world_pos + face.normal()) {
+                    Some((_, block, state)) if block == fluid => Some(state.level() + 1),
+                    _ => None,
+                })
+                .min()
+        };
+        match fed {
+            Some(fed) if fed <= MAX_LEVEL => if fed != level {
+                // the change schedules this block again to carry on from the new level
+                edit.set_block_state(block_world_pos, fluid, state.with_level(fed));
+                return;
+            },
+            _ => {
+                edit.set_block(block_world_pos, BlockType::AIR);
+                return;
+            },
+        }
+    }
+
+    let below = block_world_pos + BlockFace::Bottom.normal();
+    match edit.get_block(below) {
+        Some((_, block, _)) if replaceable(block) => {
+            edit.set_block_state(below, fluid, BlockState::default().with_level(1));
+            return;
+        },
+        // only spread out on top of something solid
+        Some((_, block, _)) if block == fluid => return,
+        None => return,
+        _ => {},
+    }
+
+    if level < MAX_LEVEL {
+        let spread = BlockState::default().with_level(level + 1);
+        for face in HORIZONTAL {
+            let neighbor = block_world_pos + face.normal();
+            match edit.get_block(neighbor) {
+                Some((_, block, _)) if replaceable(block) => {
+                    edit.set_block_state(neighbor, fluid, spread);
+                },
+                Some((_, block, state)) if block == fluid && state.level() > level + 1 => {
+                    edit.set_block_state(neighbor, fluid, spread);
+                },
+                _ => {},
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::Terrain;
+
+    const TRENCH_LENGTH: i32 = 10;
+
+    // stone up to y 31 with a water source sunk into it at x 10, a dam at x 11 and a trench
+    // running on from there
+    fn dammed_trench() -> Terrain {
+        let mut terrain = Terrain::flat(32);
+        terrain.set_block(WorldPos::new(10, 31, 10), BlockType::WATER);
+        for x in 12..12 + TRENCH_LENGTH {
+            terrain.set_block(WorldPos::new(x, 31, 10), BlockType::AIR);
+        }
+        terrain.run_ticks(FLOW_DELAY * 2);
+        terrain
+    }
+
+    // the fluid level along the trench from the source, None where there is none
+    fn levels(terrain: &Terrain) -> Vec<Option<u8>> {
+        (10..12 + TRENCH_LENGTH).map(|x| match terrain.get_block(WorldPos::new(x, 31, 10)).unwrap() {
+            (_, BlockType::WATER, state) => Some(state.level()),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn surface_drops_with_the_level() {
+        assert_eq!(surface_height(0, false), 1.0);
+        assert_eq!(surface_height(MAX_LEVEL, true), 1.0);
+        for level in 1..MAX_LEVEL {
+            assert!(surface_height(level + 1, false) < surface_height(level, false));
+        }
+        assert!(surface_height(MAX_LEVEL, false) > 0.0);
+    }
+
+    #[test]
+    fn water_flows_one_level_per_block() {
+        let mut terrain = dammed_trench();
+        assert_eq!(levels(&terrain)[..2], [Some(0), None]);
+
+        terrain.set_block(WorldPos::new(11, 31, 10), BlockType::AIR);
+        terrain.run_ticks(FLOW_DELAY * (MAX_LEVEL as u64 + 2));
+        let mut expected: Vec<_> = (0..=MAX_LEVEL).map(Some).collect();
+        expected.resize(levels(&terrain).len(), None);
+        assert_eq!(levels(&terrain), expected);
+    }
+
+    #[test]
+    fn water_falls_before_spreading() {
+        let mut terrain = dammed_trench();
+        terrain.set_block(WorldPos::new(11, 31, 10), BlockType::AIR);
+        terrain.set_block(WorldPos::new(11, 30, 10), BlockType::AIR);
+        terrain.run_ticks(FLOW_DELAY * (MAX_LEVEL as u64 + 2));
+        let below = terrain.get_block(WorldPos::new(11, 30, 10)).unwrap();
+        assert_eq!((below.1, below.2.level()), (BlockType::WATER, 1));
+        // the water at the top of the fall spreads no further
+        assert_eq!(levels(&terrain)[2..], vec![None; TRENCH_LENGTH as usize][..]);
+    }
+
+    #[test]
+    fn flowing_water_dries_up_without_its_source() {
+        let mut terrain = dammed_trench();
+        terrain.set_block(WorldPos::new(11, 31, 10), BlockType::AIR);
+        terrain.run_ticks(FLOW_DELAY * (MAX_LEVEL as u64 + 2));
+
+        terrain.set_block(WorldPos::new(10, 31, 10), BlockType::AIR);
+        terrain.run_ticks(FLOW_DELAY * (MAX_LEVEL as u64 + 2) * 4);
+        assert!(levels(&terrain).iter().all(Option::is_none));
+    }
+}
@@ -0,0 +1,54 @@
+use crate::input::InputState;
+use crate::block::{self, BlockType};
+use winit::event::VirtualKeyCode;
+
+pub const HOTBAR_SIZE: usize = 9;
+
+const SLOT_KEYS: [VirtualKeyCode; HOTBAR_SIZE] = [
+    VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3,
+    VirtualKeyCode::Key4, VirtualKeyCode::Key5, VirtualKeyCode::Key6,
+    VirtualKeyCode::Key7, VirtualKeyCode::Key8, VirtualKeyCode::Key9,
+];
+
+// blocks the hotbar starts out with, names missing from blocks.ron are left empty
+const DEFAULT_SLOTS: [&str; HOTBAR_SIZE] = [
+    "grass", "dirt", "stone", "sand", "wood", "leaves", "stone_slab", "stone_stairs", "wood_fence",
+];
+
+// Blocks the player can place, one of them selected with the number keys or the mouse wheel
+#[derive(Debug)]
+pub struct Hotbar {
+    slots: [BlockType; HOTBAR_SIZE],
+    selected: usize,
+    scroll: f32, // wheel movement not yet turned into whole slots
+}
+
+impl Hotbar {
+    pub fn new() -> Self {
+        let registry = block::registry();
+        Self {
+            slots: DEFAULT_SLOTS.map(|name| registry.by_name(name).unwrap_or(BlockType::AIR)),
+            selected: 0,
+            scroll: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, input: &InputState) {
+        if let Some(slot) = SLOT_KEYS.iter().position(|key| input.key_pressed(*key)) {
+            self.selected = slot;
+        }
+
+        // scrolling up goes to the previous slot, wrapping around at the ends
+        self.scroll -= input.scroll_delta;
+        while self.scroll.abs() >= 1.0 {
+            let step = self.scroll.signum();
+            self.selected = (self.selected as isize + step as isize).rem_euclid(HOTBAR_SIZE as isize) as usize;
+            self.scroll -= step;
+        }
+    }
+
+    // the block to place, air for an empty slot
+    pub fn selected(&self) -> BlockType {
+        self.slots[self.selected]
+    }
+}
@@ -4,6 +4,7 @@ pub struct InputState {
     keys: [bool; 163],
     mouse_buttons: [bool; 32], // kinda arbitrary max size, maybe put more thought into it later
     pub mouse_delta: (f32, f32),
+    pub scroll_delta: f32, // in lines, positive is up
 }
 
 impl InputState {
@@ -12,6 +13,7 @@ impl InputState {
             keys: [false; 163],
             mouse_buttons: [false; 32],
             mouse_delta: (0.0, 0.0),
+            scroll_delta: 0.0,
         }
     }
 
@@ -27,6 +29,11 @@ impl InputState {
         self.mouse_delta = (mouse_dx as f32, mouse_dy as f32);
     }
 
+    // wheel events can come several times a frame, they add up until the delta is reset
+    pub fn update_scroll(&mut self, delta: f32) {
+        self.scroll_delta += delta;
+    }
+
     pub fn update_mouse_button(&mut self, button: event::MouseButton, state: event::ElementState) {
         match button {
             event::MouseButton::Left => {
@@ -5,24 +5,66 @@ mod mesh;
 mod input;
 mod camera;
 mod player;
+mod hotbar;
 mod chunk;
 mod block;
+mod block_textures;
+mod block_entity;
+mod model;
 mod terrain;
+mod worldgen;
+mod biome;
+mod underground;
+mod structure;
+mod tick;
+mod fluid;
+mod falling;
+mod region;
+mod seed;
+mod coords;
+mod chunk_cache;
+mod edit;
+mod editor;
+mod vox;
 use gpu_state::GpuState;
 
 use winit::{
-    event::{Event, WindowEvent, ElementState, VirtualKeyCode, KeyboardInput},
+    event::{Event, WindowEvent, ElementState, VirtualKeyCode, KeyboardInput, MouseScrollDelta},
     event_loop::{ControlFlow, EventLoop},
     window::{WindowBuilder},
     dpi::{PhysicalPosition, LogicalSize},
 };
 use nalgebra::Vector3;
 
+const TITLE: &str = "voxel engine";
+const PIXELS_PER_LINE: f32 = 40.0; // for touchpads, which scroll in pixels instead of lines
+// Old worlds were generated from a fixed noise seed and random trees, which no world seed
+// reproduces. Chunks generated in them from now on won't match the saved terrain next to them.
+const LEGACY_SEED: u64 = 134;
+const CHUNK_CACHE_BUDGET: usize = 64 * 1024 * 1024; // bytes of unloaded edited chunks kept in memory
+
 fn main() {
     env_logger::init();
 
+    // before anything looks up a block, the world may define its own
+    let region_store = match region::RegionStore::new("world") {
+        Ok(region_store) => Some(region_store),
+        Err(e) => {
+            eprintln!("failed to open world directory, changes will not be saved: {:?}", e);
+            None
+        },
+    };
+    if let Some(region_store) = &region_store {
+        match region_store.load_blocks() {
+            Ok(Some(source)) => block::load_registry(&source),
+            Ok(None) => {},
+            Err(e) => eprintln!("failed to read world/blocks.ron, using the built-in blocks: {:?}", e),
+        }
+    }
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
+        .with_title(TITLE)
         .with_resizable(false)
         .with_inner_size(LogicalSize {
             width: 1600,
@@ -40,13 +82,28 @@ fn main() {
         gpu.config.width as f32 / gpu.config.height as f32,
         f32::to_radians(90.0), 0.1, 1000.0);
     let mut player = player::Player::new(Vector3::new(0.0, 64.0, 0.0), 10.0, 60.0);
+    let mut editor = editor::Editor::new();
 
     let thread_pool = rayon::ThreadPoolBuilder::new().build().unwrap();
-    let mut terrain = terrain::Terrain::new();
+    let seed = world_seed(region_store.as_ref());
+    let preset = match &region_store {
+        Some(region_store) => region_store.load_preset().unwrap_or_else(|e| {
+            eprintln!("failed to load world/generator.ron, using the default generator: {:?}", e);
+            worldgen::Preset::default()
+        }),
+        None => worldgen::Preset::default(),
+    };
+    let underground = world_underground(region_store.as_ref());
+    let generator = preset.build(seed, underground.clone()).unwrap_or_else(|e| {
+        eprintln!("invalid world/generator.ron, using the default generator: {:?}", e);
+        std::sync::Arc::new(worldgen::NoiseGenerator::new(seed, underground))
+    });
+    let mut terrain = terrain::Terrain::new(generator, region_store, CHUNK_CACHE_BUDGET);
     let mut terrain_mesh = terrain::TerrainMesh::new();
 
     let mut last_render_time = std::time::Instant::now();
     let mut mouse_position = PhysicalPosition::new(-1.0, -1.0);
+    let mut shown_block = None; // selected block in the window title
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
@@ -95,6 +152,13 @@ fn main() {
                         button,
                         ..
                     } => input.update_mouse_button(*button, *state),
+                    WindowEvent::MouseWheel {
+                        delta,
+                        ..
+                    } => input.update_scroll(match delta {
+                        MouseScrollDelta::LineDelta(_, y) => *y,
+                        MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                    }),
                     _ => {}
                 }
             }
@@ -102,12 +166,20 @@ fn main() {
                 let now = std::time::Instant::now();
                 let dt = now - last_render_time;
                 last_render_time = now;
-                let terrain_changes = player.update(&mut camera, dt, &input, &terrain);
+                player.update(&mut camera, dt, &input, &mut terrain);
+                editor.update(&input, &mut terrain, player.position);
 
-                let terrain_changes = terrain.update(player.chunk_position, terrain_changes, &thread_pool);
+                let terrain_changes = terrain.update(player.chunk_position, dt, &thread_pool);
                 terrain_mesh.update(&terrain_changes, &terrain, player.chunk_position, &gpu.device, &thread_pool);
 
                 input.update_mouse(0.0, 0.0); // Mouse needs to get reset at end of frame
+                input.scroll_delta = 0.0;
+
+                let selected = player.hotbar.selected();
+                if Some(selected) != shown_block {
+                    gpu.window.set_title(&format!("{} - {}", TITLE, selected.name()));
+                    shown_block = Some(selected);
+                }
                 
                 match renderer.render(&gpu, &camera, &terrain_mesh.get_opaque_meshes()[..], &terrain_mesh.get_transparent_meshes()[..]) {
                     Ok(_) => {}
@@ -118,7 +190,72 @@ fn main() {
             Event::MainEventsCleared => {
                 gpu.window.request_redraw();
             }
+            Event::LoopDestroyed => {
+                terrain.save();
+            }
             _ => (),
         }
     });
 }
+
+// Seed of the saved world, or for a new world the one given as the first argument, else a random
+// one. New worlds keep their seed in the world directory, a seed that can't be read is never
+// overwritten. Worlds saved before seeds were kept get LEGACY_SEED, so what they generate stays
+// the same from run to run.
+fn world_seed(region_store: Option<&region::RegionStore>) -> u64 {
+    let requested = std::env::args().nth(1).and_then(|arg| match arg.parse() {
+        Ok(seed) => Some(seed),
+        Err(_) => {
+            eprintln!("seed {:?} is not a number, using a random one", arg);
+            None
+        },
+    });
+
+    let region_store = match region_store {
+        Some(region_store) => region_store,
+        None => return requested.unwrap_or_else(rand::random),
+    };
+    let seed = match region_store.load_seed() {
+        Ok(Some(seed)) => {
+            if requested.is_some_and(|requested| requested != seed) {
+                eprintln!("the world already has seed {}, ignoring the one given", seed);
+            }
+            return seed;
+        },
+        Ok(None) => match region_store.has_regions() {
+            Ok(true) => {
+                eprintln!("the world was saved before it had a seed, using the legacy seed {}", LEGACY_SEED);
+                LEGACY_SEED
+            },
+            Ok(false) => requested.unwrap_or_else(rand::random),
+            // the world might be old, so the seed isn't saved and it isn't made new for good
+            Err(e) => {
+                eprintln!("failed to look for saved chunks, using a seed for this run only: {:?}", e);
+                return requested.unwrap_or_else(rand::random);
+            },
+        },
+        // the seed file is left alone, it may well be readable next time
+        Err(e) => {
+            eprintln!("failed to load the world seed, using a seed for this run only: {:?}", e);
+            return requested.unwrap_or_else(rand::random);
+        },
+    };
+    if let Err(e) = region_store.save_seed(seed) {
+        eprintln!("failed to save the world seed: {:?}", e);
+    }
+    seed
+}
+
+// The world's own underground.ron, else the built-in one. Worlds whose blocks.ron has no ores get
+// plain stone.
+fn world_underground(region_store: Option<&region::RegionStore>) -> underground::Underground {
+    match region_store.map(|region_store| region_store.load_underground()) {
+        Some(Ok(Some(underground))) => return underground,
+        Some(Err(e)) => eprintln!("invalid world/underground.ron, using the built-in one: {:?}", e),
+        _ => {},
+    }
+    underground::Underground::builtin().unwrap_or_else(|e| {
+        eprintln!("the built-in underground needs blocks this world doesn't have, leaving it plain stone: {:?}", e);
+        underground::Underground::default()
+    })
+}
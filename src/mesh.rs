@@ -8,14 +8,16 @@ pub struct MeshVertex {
     pub tex_coords: [f32; 2],
     pub normal: [f32; 3],
     pub ao: f32,
+    pub layer: u32, // of the block texture array
 }
 
 impl MeshVertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+    const ATTRIBS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
         0 => Float32x3,
         1 => Float32x2,
         2 => Float32x3,
         3 => Float32,
+        4 => Uint32,
     ];
 }
 
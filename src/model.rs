@@ -0,0 +1,276 @@
+use crate::block::{BlockFace, BlockState};
+use nalgebra::{Vector2, Vector3, vector};
+
+// Shape a block is drawn with. Positions are relative to the block's center, so a full block goes
+// from -0.5 to 0.5 on every axis.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockModel {
+    Cube,
+    // oriented box models turn to face the orientation in their state, see orient
+    Boxes(Vec<ModelBox>),
+    // two quads crossing diagonally, for plants
+    Cross,
+    // a post with rails towards neighboring fences and opaque blocks
+    Fence,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelBox {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl ModelBox {
+    // corners in sixteenths of a block from the block's lowest corner, like in blocks.ron
+    pub fn from_sixteenths(from: (u8, u8, u8), to: (u8, u8, u8)) -> Self {
+        let corner = |(x, y, z): (u8, u8, u8)| vector![x, y, z].cast::<f32>() / 16.0 - vector![0.5, 0.5, 0.5];
+        let (a, b) = (corner(from), corner(to));
+        Self {
+            min: a.inf(&b),
+            max: a.sup(&b),
+        }
+    }
+
+    fn oriented(&self, orientation: BlockFace) -> Self {
+        let (a, b) = (orient(self.min, orientation), orient(self.max, orientation));
+        Self {
+            min: a.inf(&b),
+            max: a.sup(&b),
+        }
+    }
+}
+
+// One side of a model
+pub struct ModelQuad {
+    // top left, top right, bottom left, bottom right, seen from the front
+    pub positions: [Vector3<f32>; 4],
+    pub tex_coords: [[f32; 2]; 4],
+    pub normal: [f32; 3],
+    // face of the unoriented model, picks the texture
+    pub texture_face: BlockFace,
+    // set if the quad is flush with this side of the block, it is hidden when the neighbor there
+    // is opaque
+    pub cull_face: Option<BlockFace>,
+}
+
+impl ModelQuad {
+    // whether the quad hides all of other, both being flush with the sides where their blocks touch
+    pub fn covers(&self, other: &ModelQuad) -> bool {
+        let face = match (self.cull_face, other.cull_face) {
+            (Some(face), Some(other_face)) if face == other_face.opposite() => face,
+            _ => return false,
+        };
+        // the rect each takes up on the side, as min and max of the other two axes
+        let rect = |quad: &ModelQuad| {
+            let mut min = quad.positions[0];
+            let mut max = quad.positions[0];
+            for p in &quad.positions[1..] {
+                min = min.inf(p);
+                max = max.sup(p);
+            }
+            (min, max)
+        };
+        let ((min, max), (other_min, other_max)) = (rect(self), rect(other));
+        (0..3).filter(|axis| *axis != face.axis())
+            .all(|axis| min[axis] <= other_min[axis] && max[axis] >= other_max[axis])
+    }
+}
+
+impl BlockModel {
+    // quads making up the model of a block, connects says whether a fence has a rail towards face.
+    // There are none for Cube, chunk meshes draw cubes themselves to add ambient occlusion.
+    pub fn quads(&self, state: BlockState, connects: impl Fn(BlockFace) -> bool) -> Vec<ModelQuad> {
+        let mut quads = Vec::new();
+        match self {
+            BlockModel::Cube => {},
+            BlockModel::Boxes(boxes) => {
+                let orientation = state.orientation();
+                for model_box in boxes {
+                    let world_box = model_box.oriented(orientation);
+                    for face in BlockFace::iterator() {
+                        quads.push(box_quad(&world_box, *face, model_face(*face, orientation)));
+                    }
+                }
+            },
+            BlockModel::Cross => {
+                let corners = [vector![-0.5, -0.5], vector![0.5, 0.5], vector![-0.5, 0.5], vector![0.5, -0.5]];
+                for (a, b) in [(corners[0], corners[1]), (corners[2], corners[3])] {
+                    // both sides, since back faces are culled
+                    quads.push(upright_quad(a, b));
+                    quads.push(upright_quad(b, a));
+                }
+            },
+            BlockModel::Fence => {
+                let mut boxes = vec![ModelBox::from_sixteenths((6, 0, 6), (10, 16, 10))];
+                for face in [BlockFace::Front, BlockFace::Back, BlockFace::Left, BlockFace::Right] {
+                    if connects(face) {
+                        for (bottom, top) in [(6, 9), (12, 15)] {
+                            boxes.push(ModelBox::from_sixteenths((7, bottom, 10), (9, top, 16)).oriented(face));
+                        }
+                    }
+                }
+                for world_box in boxes {
+                    for face in BlockFace::iterator() {
+                        quads.push(box_quad(&world_box, *face, *face));
+                    }
+                }
+            },
+        }
+        quads
+    }
+}
+
+// the face of the box on the given side, textured as texture_face
+fn box_quad(model_box: &ModelBox, face: BlockFace, texture_face: BlockFace) -> ModelQuad {
+    let vertices = face.get_vertices();
+    let mut positions = [Vector3::zeros(); 4];
+    let mut tex_coords = [[0.0; 2]; 4];
+    for (i, v) in vertices.iter().enumerate() {
+        // the unit cube's face has its corners at +-0.5, move them to the box's
+        positions[i] = Vector3::from_fn(|axis, _| if v.position[axis] < 0.0 {
+            model_box.min[axis]
+        } else {
+            model_box.max[axis]
+        });
+        tex_coords[i] = face.uv(positions[i]);
+    }
+
+    let normal = face.normal();
+    let on_side = positions[0][face.axis()] * normal[face.axis()] as f32 >= 0.5;
+    ModelQuad {
+        positions,
+        tex_coords,
+        normal: normal.cast::<f32>().into(),
+        texture_face,
+        cull_face: if on_side { Some(face) } else { None },
+    }
+}
+
+// full height quad from a to b, given as (x, z), facing (b - a) x up
+fn upright_quad(a: Vector2<f32>, b: Vector2<f32>) -> ModelQuad {
+    let (a, b) = (vector![a.x, 0.0, a.y], vector![b.x, 0.0, b.y]);
+    let up = vector![0.0, 0.5, 0.0];
+    let normal = (b - a).cross(&Vector3::y()).normalize();
+    ModelQuad {
+        positions: [a + up, b + up, a - up, b - up],
+        tex_coords: [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]],
+        normal: normal.into(),
+        texture_face: BlockFace::Front,
+        cull_face: None,
+    }
+}
+
+// Models take the orientation in the state as the way they face, unlike cubes, which tip over
+// towards it (see BlockFace::model_face). Their front (+z) turns towards a side, Top leaves them
+// as they are, so it looks the same as Front, and Bottom turns them upside down around z, their
+// front staying at the front. So stairs placed against a block face away from it, and stairs
+// placed on top or below one face +z.
+fn orient(p: Vector3<f32>, orientation: BlockFace) -> Vector3<f32> {
+    match orientation {
+        BlockFace::Top | BlockFace::Front => p,
+        BlockFace::Bottom => vector![-p.x, -p.y, p.z],
+        BlockFace::Back => vector![-p.x, p.y, -p.z],
+        BlockFace::Right => vector![p.z, p.y, -p.x],
+        BlockFace::Left => vector![-p.z, p.y, p.x],
+    }
+}
+
+// face of the unoriented model that ends up on the given side
+fn model_face(face: BlockFace, orientation: BlockFace) -> BlockFace {
+    *BlockFace::iterator()
+        .find(|m| orient(m.normal().cast::<f32>(), orientation) == face.normal().cast::<f32>())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slab(bottom: u8, top: u8) -> BlockModel {
+        BlockModel::Boxes(vec![ModelBox::from_sixteenths((0, bottom, 0), (16, top, 16))])
+    }
+
+    fn quad(quads: &[ModelQuad], normal: BlockFace) -> &ModelQuad {
+        let normal = normal.normal().cast::<f32>();
+        quads.iter().find(|quad| Vector3::from(quad.normal) == normal).unwrap()
+    }
+
+    #[test]
+    fn box_faces_are_culled_only_on_the_block_sides() {
+        let quads = slab(0, 8).quads(BlockState::default(), |_| false);
+        assert_eq!(quads.len(), 6);
+        assert_eq!(quad(&quads, BlockFace::Top).cull_face, None);
+        assert_eq!(quad(&quads, BlockFace::Bottom).cull_face, Some(BlockFace::Bottom));
+        let side = quad(&quads, BlockFace::Right);
+        assert_eq!(side.cull_face, Some(BlockFace::Right));
+        assert!(side.positions.iter().all(|p| p.x == 0.5 && (-0.5..=0.0).contains(&p.y)));
+        // halfway up the texture too
+        assert!(side.tex_coords.iter().all(|uv| (0.5..=1.0).contains(&uv[1])));
+    }
+
+    #[test]
+    fn touching_model_sides_cover_each_other() {
+        let bottom = slab(0, 8).quads(BlockState::default(), |_| false);
+        let top = slab(8, 16).quads(BlockState::default(), |_| false);
+        assert!(quad(&bottom, BlockFace::Left).covers(quad(&bottom, BlockFace::Right)));
+        assert!(!quad(&top, BlockFace::Left).covers(quad(&bottom, BlockFace::Right)));
+        // sides facing the same way never do
+        assert!(!quad(&bottom, BlockFace::Right).covers(quad(&bottom, BlockFace::Right)));
+    }
+
+    #[test]
+    fn oriented_boxes_turn_their_front() {
+        // the front half of the block, textured as the front where it faces
+        let half = BlockModel::Boxes(vec![ModelBox::from_sixteenths((0, 0, 8), (16, 16, 16))]);
+        let state = BlockState::default().with_orientation(BlockFace::Right);
+        let quads = half.quads(state, |_| false);
+        let right = quad(&quads, BlockFace::Right);
+        assert_eq!(right.texture_face, BlockFace::Front);
+        assert_eq!(right.cull_face, Some(BlockFace::Right));
+        assert!(quad(&quads, BlockFace::Left).positions.iter().all(|p| p.x == 0.0));
+
+        let upside_down = half.quads(BlockState::default().with_orientation(BlockFace::Bottom), |_| false);
+        assert_eq!(quad(&upside_down, BlockFace::Top).texture_face, BlockFace::Bottom);
+        assert_eq!(model_face(BlockFace::Front, BlockFace::Back), BlockFace::Back);
+    }
+
+    #[test]
+    fn models_face_their_orientation() {
+        // a step at the back, like the stairs in blocks.ron
+        let stairs = BlockModel::Boxes(vec![
+            ModelBox::from_sixteenths((0, 0, 0), (16, 8, 16)),
+            ModelBox::from_sixteenths((0, 8, 8), (16, 16, 16)),
+        ]);
+        let positions = |orientation| {
+            let quads = stairs.quads(BlockState::default().with_orientation(orientation), |_| false);
+            quads.iter().map(|quad| (quad.positions, quad.texture_face)).collect::<Vec<_>>()
+        };
+        // on top of a block, or against its front, both face +z
+        assert_eq!(positions(BlockFace::Top), positions(BlockFace::Front));
+        assert_ne!(positions(BlockFace::Top), positions(BlockFace::Back));
+
+        // upside down keeps the step at the front, now hanging from the top
+        let quads = stairs.quads(BlockState::default().with_orientation(BlockFace::Bottom), |_| false);
+        let step = quads.iter().filter(|quad| quad.cull_face == Some(BlockFace::Bottom)).collect::<Vec<_>>();
+        assert_eq!(step.len(), 1);
+        assert!(step[0].positions.iter().all(|p| p.z >= 0.0));
+        // turned, not mirrored, so the left side is textured as the right
+        assert_eq!(model_face(BlockFace::Left, BlockFace::Bottom), BlockFace::Right);
+    }
+
+    #[test]
+    fn fences_grow_rails_towards_connections() {
+        let post = BlockModel::Fence.quads(BlockState::default(), |_| false);
+        assert_eq!(post.len(), 6);
+        assert!(post.iter().all(|quad| matches!(quad.cull_face, None | Some(BlockFace::Top) | Some(BlockFace::Bottom))));
+
+        let connected = BlockModel::Fence.quads(BlockState::default(), |face| face == BlockFace::Front);
+        assert_eq!(connected.len(), 6 + 2*6);
+        assert_eq!(connected.iter().filter(|quad| quad.cull_face == Some(BlockFace::Front)).count(), 2);
+    }
+
+    #[test]
+    fn cubes_have_no_model_quads() {
+        assert!(BlockModel::Cube.quads(BlockState::default(), |_| true).is_empty());
+    }
+}
@@ -1,8 +1,10 @@
 use crate::input::InputState;
 use crate::camera::Camera;
-use crate::terrain::{Terrain, TerrainChanges};
-use crate::chunk;
-use crate::block::BlockType;
+use crate::terrain::Terrain;
+use crate::coords::{WorldPos, ChunkPos};
+use crate::block::{BlockType, BlockFace, BlockState};
+use crate::hotbar::Hotbar;
+use crate::block_entity::BlockEntity;
 use winit::event::VirtualKeyCode;
 use winit::event;
 use nalgebra::{Vector3, vector};
@@ -10,33 +12,37 @@ use std::time::Duration;
 use std::f32::consts::FRAC_PI_2;
 
 const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
+const REACH: f32 = 5.0; // how far away blocks can be broken and placed
+// the player's body, around the camera at eye height
+const BODY_HALF_WIDTH: f32 = 0.3;
+const BODY_HEIGHT: f32 = 1.8;
+const EYE_HEIGHT: f32 = 1.6;
 
 #[derive(Debug)]
 pub struct Player {
     pub position: Vector3<f32>,
-    pub chunk_position: Vector3<i32>,
+    pub chunk_position: ChunkPos,
     speed: f32,
     sensitivity: f32,
     mouse_p: bool,
+    right_mouse_p: bool,
+    pub hotbar: Hotbar,
 }
 
 impl Player {
     pub fn new(position: Vector3<f32>, speed: f32, sensitivity: f32) -> Self {
-        let chunk_position = vector![
-            f32::floor(position[0] / chunk::CHUNK_SIZE as f32) as i32,
-            f32::floor(position[1] / chunk::CHUNK_SIZE as f32) as i32,
-            f32::floor(position[2] / chunk::CHUNK_SIZE as f32) as i32,
-        ];
         Self {
             position,
-            chunk_position,
+            chunk_position: ChunkPos::containing(position),
             speed,
             sensitivity,
             mouse_p: false,
+            right_mouse_p: false,
+            hotbar: Hotbar::new(),
         }
     }
 
-    pub fn update(&mut self, camera: &mut Camera, dt: Duration, input: &InputState, terrain: &Terrain) -> TerrainChanges {
+    pub fn update(&mut self, camera: &mut Camera, dt: Duration, input: &InputState, terrain: &mut Terrain) {
         let dt = dt.as_secs_f32();
 
         // Move forward/backward and left/right
@@ -77,51 +83,244 @@ impl Player {
         camera.pitch += f32::to_radians(-input.mouse_delta.1) * self.sensitivity * dt;
 
         // Keep the camera's angle from going too high/low.
-        if camera.pitch < -SAFE_FRAC_PI_2 {
-            camera.pitch = -SAFE_FRAC_PI_2;
-        } else if camera.pitch > SAFE_FRAC_PI_2 {
-            camera.pitch = SAFE_FRAC_PI_2;
-        }
+        camera.pitch = camera.pitch.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2);
+
+        self.chunk_position = ChunkPos::containing(self.position);
 
-        self.chunk_position = vector![
-            f32::floor((self.position[0]-0.5) / chunk::CHUNK_SIZE as f32) as i32,
-            f32::floor((self.position[1]-0.5) / chunk::CHUNK_SIZE as f32) as i32,
-            f32::floor((self.position[2]-0.5) / chunk::CHUNK_SIZE as f32) as i32,
-        ];
+        self.hotbar.update(input);
+
+        let dir = Vector3::new(
+            camera.yaw.cos()*camera.pitch.cos(),
+            camera.pitch.sin(),
+            camera.yaw.sin()*camera.pitch.cos(),
+        ).normalize();
 
-        let mut terrain_changes = TerrainChanges::new();
         if input.mouse_pressed(event::MouseButton::Left) {
             if !self.mouse_p {
                 self.mouse_p = true;
-                let dir = Vector3::new(
-                    camera.yaw.cos()*camera.pitch.cos(),
-                    camera.pitch.sin(),
-                    camera.yaw.sin()*camera.pitch.cos(),
-                ).normalize();
-                for t in 0..50 {
-                    let block_world_pos = vector![
-                        (dir.x * (t as f32 / 10.0) + self.position.x).round() as i32,
-                        (dir.y * (t as f32 / 10.0) + self.position.y).round() as i32,
-                        (dir.z * (t as f32 / 10.0) + self.position.z).round() as i32,
-                    ];
-                    let c_pos = vector![
-                        f32::floor(block_world_pos.x as f32 / chunk::CHUNK_SIZE as f32) as i32,
-                        f32::floor(block_world_pos.y as f32 / chunk::CHUNK_SIZE as f32) as i32,
-                        f32::floor(block_world_pos.z as f32 / chunk::CHUNK_SIZE as f32) as i32,
-                    ];
-                    if let Some((block_pos, block)) = terrain.get_block(block_world_pos) {
-                        if block != BlockType::Air {
-                            terrain_changes.modified_chunks.entry(c_pos)
-                                .and_modify(|blocks| blocks.push((block_pos, BlockType::Air)))
-                                .or_insert([(block_pos, BlockType::Air)].to_vec());
-                            break;
-                        }
+                if let Some((block_world_pos, _)) = raycast(terrain, self.position, dir, REACH) {
+                    if !take(terrain, block_world_pos) {
+                        terrain.set_block(block_world_pos, BlockType::AIR);
                     }
                 }
             }
         } else {
             self.mouse_p = false;
         }
-        terrain_changes
+
+        if input.mouse_pressed(event::MouseButton::Right) {
+            if !self.right_mouse_p {
+                self.right_mouse_p = true;
+                if let Some((block_world_pos, Some(face))) = raycast(terrain, self.position, dir, REACH) {
+                    if !self.store(terrain, block_world_pos) {
+                        self.place(terrain, block_world_pos + face.normal(), face);
+                    }
+                }
+            }
+        } else {
+            self.right_mouse_p = false;
+        }
+    }
+
+    // places the selected block against face of the block next to it. Only blocks without
+    // collision, like air, water and plants, get replaced
+    fn place(&self, terrain: &mut Terrain, block_world_pos: WorldPos, face: BlockFace) {
+        let block = self.hotbar.selected();
+        if block == BlockType::AIR {
+            return;
+        }
+        match terrain.get_block(block_world_pos) {
+            Some((_, old, _)) if !old.properties().collision => {},
+            _ => return,
+        }
+        if block.properties().collision && self.overlaps(block_world_pos) {
+            return;
+        }
+
+        let mut state = BlockState::default().with_player_placed(true);
+        if block.oriented() {
+            state = state.with_orientation(face);
+        }
+        terrain.set_block_state(block_world_pos, block, state);
+    }
+
+    // puts one of the selected block into the container at block_world_pos, false if it isn't one
+    fn store(&self, terrain: &mut Terrain, block_world_pos: WorldPos) -> bool {
+        let block = self.hotbar.selected();
+        terrain.edit(|e| match e.block_entity_mut(block_world_pos) {
+            Some(BlockEntity::Container(container)) => {
+                if block != BlockType::AIR && container.add(block, 1) == 0 {
+                    log::info!("stored {}", block.name());
+                }
+                true
+            },
+            _ => false,
+        })
+    }
+
+    fn overlaps(&self, block_world_pos: WorldPos) -> bool {
+        let body_min = self.position - vector![BODY_HALF_WIDTH, EYE_HEIGHT, BODY_HALF_WIDTH];
+        let body_max = body_min + vector![BODY_HALF_WIDTH * 2.0, BODY_HEIGHT, BODY_HALF_WIDTH * 2.0];
+        let center = block_world_pos.center();
+        (0..3).all(|axis| center[axis] + 0.5 > body_min[axis] && center[axis] - 0.5 < body_max[axis])
+    }
+}
+
+// takes the last stack out of the container at block_world_pos, false if there is nothing to
+// take, so empty containers get broken
+fn take(terrain: &mut Terrain, block_world_pos: WorldPos) -> bool {
+    let slot = match terrain.block_entity(block_world_pos) {
+        Some(BlockEntity::Container(container)) => container.slots.iter().rposition(Option::is_some),
+        _ => None,
+    };
+    let stack = slot.and_then(|slot| terrain.edit(|e| match e.block_entity_mut(block_world_pos) {
+        Some(BlockEntity::Container(container)) => container.take(slot),
+        _ => None,
+    }));
+    if let Some(stack) = stack {
+        log::info!("took {} {}", stack.count, stack.block.name());
+    }
+    stack.is_some()
+}
+
+// The first block along the ray that can be aimed at, and the face the ray entered it through.
+// Steps from block to block so no corners get skipped. There is no face if the ray starts
+// inside the block.
+fn raycast(terrain: &Terrain, origin: Vector3<f32>, dir: Vector3<f32>, max_distance: f32) -> Option<(WorldPos, Option<BlockFace>)> {
+    // entering through the lower face on an axis when going up it, the upper when going down
+    const ENTERED: [[BlockFace; 2]; 3] = [
+        [BlockFace::Left, BlockFace::Right],
+        [BlockFace::Bottom, BlockFace::Top],
+        [BlockFace::Back, BlockFace::Front],
+    ];
+
+    let mut block_world_pos = WorldPos::containing(origin);
+    let mut face = None;
+    let step = dir.map(|d| if d > 0.0 { 1 } else { -1 });
+    // distance along the ray to the next block boundary on each axis, and between boundaries
+    let mut t_max = Vector3::zeros();
+    let t_delta = dir.map(|d| 1.0 / d.abs());
+    let center = block_world_pos.center();
+    for axis in 0..3 {
+        let boundary = center[axis] + 0.5 * step[axis] as f32;
+        t_max[axis] = if dir[axis] == 0.0 {
+            f32::INFINITY
+        } else {
+            (boundary - origin[axis]).abs() * t_delta[axis]
+        };
+    }
+
+    loop {
+        if let Some((_, block, _)) = terrain.get_block(block_world_pos) {
+            let properties = block.properties();
+            // aimed through fluids, but not through other see through blocks like ice
+            if properties.visible() && !properties.fluid {
+                return Some((block_world_pos, face));
+            }
+        }
+
+        let axis = t_max.imin();
+        if t_max[axis] > max_distance {
+            return None;
+        }
+        t_max[axis] += t_delta[axis];
+        let mut offset = Vector3::zeros();
+        offset[axis] = step[axis];
+        block_world_pos = block_world_pos + offset;
+        face = Some(ENTERED[axis][(step[axis] < 0) as usize]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOWN: Vector3<f32> = vector![0.0, -1.0, 0.0];
+
+    #[test]
+    fn looking_down_hits_the_top_face() {
+        let terrain = Terrain::flat(32);
+        let hit = raycast(&terrain, vector![10.2, 34.0, 10.7], DOWN, REACH);
+        assert_eq!(hit, Some((WorldPos::new(10, 31, 11), Some(BlockFace::Top))));
+
+        let slanted = vector![1.0, -1.0, 0.0].normalize();
+        let hit = raycast(&terrain, vector![10.0, 33.2, 10.0], slanted, REACH);
+        assert_eq!(hit, Some((WorldPos::new(12, 31, 10), Some(BlockFace::Top))));
+    }
+
+    #[test]
+    fn side_faces_are_entered_against_the_ray() {
+        let mut terrain = Terrain::flat(32);
+        terrain.set_block(WorldPos::new(14, 32, 10), BlockType::STONE);
+        terrain.set_block(WorldPos::new(6, 32, 10), BlockType::STONE);
+        let origin = vector![10.0, 32.0, 10.0];
+        assert_eq!(raycast(&terrain, origin, vector![1.0, 0.0, 0.0], REACH), Some((WorldPos::new(14, 32, 10), Some(BlockFace::Left))));
+        assert_eq!(raycast(&terrain, origin, vector![-1.0, 0.0, 0.0], REACH), Some((WorldPos::new(6, 32, 10), Some(BlockFace::Right))));
+    }
+
+    #[test]
+    fn rays_stop_at_their_reach() {
+        let terrain = Terrain::flat(32);
+        assert_eq!(raycast(&terrain, vector![10.0, 40.0, 10.0], DOWN, REACH), None);
+        assert_eq!(raycast(&terrain, vector![10.0, 32.0, 10.0], vector![0.0, 1.0, 0.0], REACH), None);
+    }
+
+    #[test]
+    fn rays_start_inside_a_block_without_a_face() {
+        let terrain = Terrain::flat(32);
+        assert_eq!(raycast(&terrain, vector![10.0, 20.0, 10.0], DOWN, REACH), Some((WorldPos::new(10, 20, 10), None)));
+    }
+
+    #[test]
+    fn rays_go_through_fluids() {
+        let mut terrain = Terrain::flat(32);
+        terrain.set_block(WorldPos::new(10, 32, 10), BlockType::WATER);
+        terrain.set_block(WorldPos::new(10, 33, 10), BlockType::WATER);
+        let hit = raycast(&terrain, vector![10.0, 35.0, 10.0], DOWN, REACH);
+        assert_eq!(hit, Some((WorldPos::new(10, 31, 10), Some(BlockFace::Top))));
+    }
+
+    #[test]
+    fn placing_goes_against_the_face() {
+        let mut terrain = Terrain::flat(32);
+        let player = Player::new(vector![10.0, 34.0, 10.0], 1.0, 1.0);
+        let block = player.hotbar.selected();
+        player.place(&mut terrain, WorldPos::new(12, 32, 10), BlockFace::Top);
+        let (_, placed, state) = terrain.get_block(WorldPos::new(12, 32, 10)).unwrap();
+        assert_eq!(placed, block);
+        assert!(state.player_placed());
+
+        // solid blocks are not replaced
+        player.place(&mut terrain, WorldPos::new(12, 31, 10), BlockFace::Top);
+        assert_eq!(terrain.get_block(WorldPos::new(12, 31, 10)).unwrap().1, BlockType::STONE);
+    }
+
+    #[test]
+    fn blocks_are_not_placed_inside_the_player() {
+        let mut terrain = Terrain::flat(32);
+        // eyes at 33.6 put the feet at 32, in the block right above the ground
+        let player = Player::new(vector![10.0, 33.6, 10.0], 1.0, 1.0);
+        for y in [32, 33] {
+            player.place(&mut terrain, WorldPos::new(10, y, 10), BlockFace::Top);
+            assert_eq!(terrain.get_block(WorldPos::new(10, y, 10)).unwrap().1, BlockType::AIR);
+        }
+        // diagonally next to the player is fine
+        player.place(&mut terrain, WorldPos::new(11, 32, 11), BlockFace::Top);
+        assert_ne!(terrain.get_block(WorldPos::new(11, 32, 11)).unwrap().1, BlockType::AIR);
+    }
+
+    #[test]
+    fn containers_are_emptied_before_they_break() {
+        let mut terrain = Terrain::flat(32);
+        let chest = crate::block::registry().by_name("chest").unwrap();
+        let pos = WorldPos::new(10, 32, 10);
+        terrain.set_block(pos, chest);
+        let player = Player::new(vector![10.0, 34.0, 12.0], 1.0, 1.0);
+        assert!(player.store(&mut terrain, pos));
+        assert!(!player.store(&mut terrain, WorldPos::new(10, 31, 10)));
+
+        assert!(take(&mut terrain, pos));
+        assert!(!take(&mut terrain, pos));
+        assert_eq!(terrain.get_block(pos).unwrap().1, chest);
     }
 }
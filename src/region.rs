@@ -0,0 +1,315 @@
+use crate::chunk::Chunk;
+use crate::coords::ChunkPos;
+use crate::underground::Underground;
+use crate::worldgen::Preset;
+use flate2::{Compression, read::ZlibDecoder, write::ZlibEncoder};
+use anyhow::{Context, Result, bail};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+pub const REGION_SIZE: i32 = 16; // chunks per region along each axis
+const REGION_VOLUME: usize = (REGION_SIZE*REGION_SIZE*REGION_SIZE) as usize;
+const HEADER_SIZE: usize = REGION_VOLUME * 8;
+
+// A region file starts with a header of REGION_VOLUME (offset: u32, length: u32) entries, one per
+// chunk, followed by the zlib compressed chunk data. A length of 0 means the chunk was never saved.
+pub struct RegionStore {
+    dir: PathBuf,
+    // saves read, change and rewrite whole region files, so only one may run at a time
+    save_lock: Mutex<()>,
+}
+
+impl RegionStore {
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            save_lock: Mutex::new(()),
+        })
+    }
+
+    // the world's seed, None for a new world
+    pub fn load_seed(&self) -> Result<Option<u64>> {
+        match fs::read_to_string(self.dir.join("seed")) {
+            Ok(seed) => Ok(Some(seed.trim().parse().context("invalid seed file")?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save_seed(&self, seed: u64) -> Result<()> {
+        fs::write(self.dir.join("seed"), format!("{}\n", seed))?;
+        Ok(())
+    }
+
+    // whether any chunks were ever saved, worlds from before seeds were kept have some but no seed
+    pub fn has_regions(&self) -> Result<bool> {
+        for entry in fs::read_dir(&self.dir)? {
+            if entry?.path().extension().is_some_and(|extension| extension == "region") {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // the world's own block definitions in place of the built-in blocks.ron, if it has any
+    pub fn load_blocks(&self) -> Result<Option<String>> {
+        match fs::read_to_string(self.dir.join("blocks.ron")) {
+            Ok(source) => Ok(Some(source)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // how the world's chunks are generated, the noise terrain unless the directory has a
+    // generator.ron saying otherwise
+    pub fn load_preset(&self) -> Result<Preset> {
+        match fs::read_to_string(self.dir.join("generator.ron")) {
+            Ok(source) => Preset::from_ron(&source),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Preset::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // the world's own strata and ores in place of the built-in underground.ron, if it has any
+    pub fn load_underground(&self) -> Result<Option<Underground>> {
+        match fs::read_to_string(self.dir.join("underground.ron")) {
+            Ok(source) => Ok(Some(Underground::from_ron(&source)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // regions are addressed like chunks, one region position step is REGION_SIZE chunks
+    fn region_pos(chunk_pos: ChunkPos) -> ChunkPos {
+        ChunkPos::new(
+            chunk_pos.x.div_euclid(REGION_SIZE),
+            chunk_pos.y.div_euclid(REGION_SIZE),
+            chunk_pos.z.div_euclid(REGION_SIZE),
+        )
+    }
+
+    // position of the chunk's entry in its region's header
+    fn entry_index(chunk_pos: ChunkPos) -> usize {
+        let x = chunk_pos.x.rem_euclid(REGION_SIZE);
+        let y = chunk_pos.y.rem_euclid(REGION_SIZE);
+        let z = chunk_pos.z.rem_euclid(REGION_SIZE);
+        (x + REGION_SIZE*y + REGION_SIZE*REGION_SIZE*z) as usize
+    }
+
+    fn region_path(&self, region_pos: ChunkPos) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.{}.region", region_pos.x, region_pos.y, region_pos.z))
+    }
+
+    // Ok(None) if the chunk has never been saved
+    pub fn load_chunk(&self, chunk_pos: ChunkPos) -> Result<Option<Chunk>> {
+        let path = self.region_path(Self::region_pos(chunk_pos));
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut entry = [0; 8];
+        file.seek(SeekFrom::Start((Self::entry_index(chunk_pos) * 8) as u64))?;
+        file.read_exact(&mut entry)?;
+        let offset = u32::from_le_bytes(entry[0..4].try_into().unwrap());
+        let length = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+        if length == 0 {
+            return Ok(None);
+        }
+
+        let mut compressed = vec![0; length as usize];
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.read_exact(&mut compressed)?;
+        let mut bytes = Vec::new();
+        ZlibDecoder::new(&compressed[..]).read_to_end(&mut bytes)?;
+
+        Chunk::from_bytes(&bytes)
+            .with_context(|| format!("chunk {:?} in {}", chunk_pos, path.display()))
+            .map(Some)
+    }
+
+    // every region is written once, however many of the chunks are in it. Safe to call from
+    // several threads.
+    pub fn save_chunks(&self, chunks: &[(ChunkPos, &Chunk)]) -> Result<()> {
+        let _guard = self.save_lock.lock().unwrap();
+        let mut regions: HashMap<ChunkPos, Vec<(ChunkPos, &Chunk)>> = HashMap::new();
+        for (chunk_pos, chunk) in chunks {
+            regions.entry(Self::region_pos(*chunk_pos)).or_default().push((*chunk_pos, *chunk));
+        }
+
+        for (region_pos, chunks) in regions {
+            self.save_region(region_pos, &chunks)?;
+        }
+        Ok(())
+    }
+
+    // rewrites the whole region file, going through a temporary file so worker threads loading
+    // from the same region never see it half written
+    fn save_region(&self, region_pos: ChunkPos, chunks: &[(ChunkPos, &Chunk)]) -> Result<()> {
+        let path = self.region_path(region_pos);
+        let mut entries = match fs::read(&path) {
+            Ok(bytes) => Self::read_entries(&bytes)
+                .with_context(|| format!("reading {}", path.display()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![Vec::new(); REGION_VOLUME],
+            Err(e) => return Err(e.into()),
+        };
+
+        for (chunk_pos, chunk) in chunks {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&chunk.to_bytes())?;
+            entries[Self::entry_index(*chunk_pos)] = encoder.finish()?;
+        }
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        let mut offset = HEADER_SIZE;
+        for entry in &entries {
+            header.extend_from_slice(&(offset as u32).to_le_bytes());
+            header.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+            offset += entry.len();
+        }
+
+        let tmp_path = path.with_extension("region.tmp");
+        {
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(&header)?;
+            for entry in &entries {
+                file.write_all(entry)?;
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    // compressed data of every chunk in a region file, empty for chunks that were never saved
+    fn read_entries(bytes: &[u8]) -> Result<Vec<Vec<u8>>> {
+        if bytes.len() < HEADER_SIZE {
+            bail!("region file header is truncated");
+        }
+
+        let mut entries = Vec::with_capacity(REGION_VOLUME);
+        for header_entry in bytes[..HEADER_SIZE].chunks_exact(8) {
+            let offset = u32::from_le_bytes(header_entry[0..4].try_into().unwrap()) as usize;
+            let length = u32::from_le_bytes(header_entry[4..8].try_into().unwrap()) as usize;
+            if length == 0 {
+                entries.push(Vec::new());
+                continue;
+            }
+            match bytes.get(offset..offset + length) {
+                Some(data) => entries.push(data.to_vec()),
+                None => bail!("region entry at {} runs past the end of the file", offset),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockType;
+
+    // a fresh directory for every test, so they can run in parallel
+    fn store(name: &str) -> RegionStore {
+        let dir = std::env::temp_dir().join(format!("voxel-engine-region-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        RegionStore::new(dir).unwrap()
+    }
+
+    // mostly air, so the palette has more than one block
+    fn chunk(block: BlockType) -> Chunk {
+        let mut chunk = Chunk::filled(BlockType::AIR);
+        chunk.set_block(block, crate::coords::LocalPos::new(1, 2, 3));
+        chunk
+    }
+
+    fn assert_loads(store: &RegionStore, pos: ChunkPos, expected: &Chunk) {
+        let loaded = store.load_chunk(pos).unwrap().expect("chunk was saved");
+        assert!(loaded.iter().eq(expected.iter()));
+    }
+
+    #[test]
+    fn round_trip_chunks() {
+        let store = store("round_trip");
+        // two in one region, one in the region below the origin
+        let chunks = [
+            (ChunkPos::new(0, 0, 0), chunk(BlockType::STONE)),
+            (ChunkPos::new(5, 3, 15), chunk(BlockType::SAND)),
+            (ChunkPos::new(-1, -1, -1), chunk(BlockType::WOOD)),
+        ];
+        let refs: Vec<_> = chunks.iter().map(|(pos, chunk)| (*pos, chunk)).collect();
+        store.save_chunks(&refs).unwrap();
+        for (pos, chunk) in &chunks {
+            assert_loads(&store, *pos, chunk);
+        }
+
+        // saving more keeps the chunks already in the region
+        let later = chunk(BlockType::DIRT);
+        store.save_chunks(&[(ChunkPos::new(1, 0, 0), &later)]).unwrap();
+        assert_loads(&store, ChunkPos::new(1, 0, 0), &later);
+        assert_loads(&store, ChunkPos::new(0, 0, 0), &chunks[0].1);
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn unsaved_chunks_load_as_none() {
+        let store = store("unsaved");
+        assert!(store.load_chunk(ChunkPos::new(0, 0, 0)).unwrap().is_none());
+        let saved = chunk(BlockType::STONE);
+        store.save_chunks(&[(ChunkPos::new(0, 0, 0), &saved)]).unwrap();
+        // in a region file that exists
+        assert!(store.load_chunk(ChunkPos::new(2, 0, 0)).unwrap().is_none());
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn corrupt_entry_fails_alone() {
+        let store = store("corrupt");
+        let (good, bad) = (ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 0));
+        let good_chunk = chunk(BlockType::STONE);
+        store.save_chunks(&[(good, &good_chunk), (bad, &chunk(BlockType::SAND))]).unwrap();
+
+        // overwrite the start of the bad chunk's compressed data
+        let path = store.region_path(RegionStore::region_pos(bad));
+        let mut bytes = fs::read(&path).unwrap();
+        let entry = RegionStore::entry_index(bad) * 8;
+        let offset = u32::from_le_bytes(bytes[entry..entry + 4].try_into().unwrap()) as usize;
+        bytes[offset..offset + 4].copy_from_slice(&[0xff; 4]);
+        fs::write(&path, bytes).unwrap();
+
+        assert!(store.load_chunk(bad).is_err());
+        assert_loads(&store, good, &good_chunk);
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn seedless_worlds_are_told_apart_from_new_ones() {
+        let store = store("seedless");
+        assert!(!store.has_regions().unwrap());
+        store.save_seed(7).unwrap();
+        assert!(!store.has_regions().unwrap());
+        store.save_chunks(&[(ChunkPos::new(0, 0, 0), &chunk(BlockType::STONE))]).unwrap();
+        assert!(store.has_regions().unwrap());
+        assert_eq!(store.load_seed().unwrap(), Some(7));
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn worlds_can_override_the_underground() {
+        let store = store("underground");
+        assert!(store.load_underground().unwrap().is_none());
+        fs::write(store.dir.join("underground.ron"), "(strata: [], ores: [])").unwrap();
+        assert!(store.load_underground().unwrap().unwrap().ores.is_empty());
+        fs::write(store.dir.join("underground.ron"), "(strata: [], ores: [(block: \"nothing\", min_y: 0, max_y: 1, size: 1, frequency: 1.0)])").unwrap();
+        assert!(store.load_underground().is_err());
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+}
@@ -2,6 +2,7 @@ use crate::gpu_state;
 use crate::mesh;
 use crate::texture;
 use crate::camera;
+use crate::block_textures;
 
 pub trait Vertex {
     fn desc() -> wgpu::VertexBufferLayout<'static>;
@@ -15,7 +16,7 @@ pub struct GlobalUniforms {
 
 pub struct Renderer {
     depth_texture: texture::Texture,
-    texture_atlas_bind_group: wgpu::BindGroup,
+    block_textures_bind_group: wgpu::BindGroup,
     global_uniform_buffer: wgpu::Buffer,
     global_uniform_bind_group: wgpu::BindGroup,
     render_pipeline: wgpu::RenderPipeline,
@@ -25,10 +26,9 @@ impl Renderer {
     pub fn new(gpu: &gpu_state::GpuState) -> Self {
         let depth_texture = texture::Texture::create_depth_texture(gpu, "depth_texture");
 
-        let atlas_bytes = include_bytes!("block-atlas.png");
-        let texture_atlas = texture::Texture::from_bytes(gpu, atlas_bytes, "block-atlas.png").unwrap();
+        let block_textures = texture::Texture::from_layers(gpu, block_textures::block_textures(), "block_textures");
 
-        let texture_atlas_bind_group_layout = gpu.device.create_bind_group_layout(
+        let block_textures_bind_group_layout = gpu.device.create_bind_group_layout(
             &wgpu::BindGroupLayoutDescriptor {
                 entries: &[
                     wgpu::BindGroupLayoutEntry {
@@ -36,7 +36,7 @@ impl Renderer {
                         visibility: wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Texture {
                             multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
                             sample_type: wgpu::TextureSampleType::Float { filterable: true },
                         },
                         count: None,
@@ -48,24 +48,24 @@ impl Renderer {
                         count: None,
                     },
                 ],
-                label: Some("texture_atlas_bind_group_layout"),
+                label: Some("block_textures_bind_group_layout"),
             }
         );
 
-        let texture_atlas_bind_group = gpu.device.create_bind_group(
+        let block_textures_bind_group = gpu.device.create_bind_group(
             &wgpu::BindGroupDescriptor {
-                layout: &texture_atlas_bind_group_layout,
+                layout: &block_textures_bind_group_layout,
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&texture_atlas.view),
+                        resource: wgpu::BindingResource::TextureView(&block_textures.view),
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&texture_atlas.sampler),
+                        resource: wgpu::BindingResource::Sampler(&block_textures.sampler),
                     },
                 ],
-                label: Some("texture_atlas_bind_group"),
+                label: Some("block_textures_bind_group"),
             }
         );
 
@@ -115,7 +115,7 @@ impl Renderer {
             &wgpu::PipelineLayoutDescriptor {
                 label: Some("render_pipeline_layout"),
                 bind_group_layouts: &[
-                    &texture_atlas_bind_group_layout,
+                    &block_textures_bind_group_layout,
                     &global_uniform_bind_group_layout,
                 ],
                 push_constant_ranges: &[],
@@ -169,7 +169,7 @@ impl Renderer {
 
         Self {
             depth_texture,
-            texture_atlas_bind_group,
+            block_textures_bind_group,
             global_uniform_buffer,
             global_uniform_bind_group,
             render_pipeline,
@@ -221,7 +221,7 @@ impl Renderer {
             );
 
             render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.texture_atlas_bind_group, &[]);
+            render_pass.set_bind_group(0, &self.block_textures_bind_group, &[]);
             render_pass.set_bind_group(1, &self.global_uniform_bind_group, &[]);
 
             for mesh in meshes {
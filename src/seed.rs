@@ -0,0 +1,47 @@
+// Everything random in world generation is derived from the world seed with these, never from
+// thread_rng, so a chunk comes out the same however often, and on whichever thread, it is made.
+// Deliberately independent of the rand crate, whose value streams may change between versions.
+
+// splitmix64's finalizer, scrambles every input bit into every output bit
+pub fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+// a seed for one use of randomness, like one noise layer, salts keep uses apart
+pub fn derive(seed: u64, salt: u64) -> u64 {
+    mix(seed ^ mix(salt))
+}
+
+// seed for the block at (x, y, z)
+pub fn position_hash(seed: u64, x: i32, y: i32, z: i32) -> u64 {
+    let mut h = mix(seed);
+    for v in [x, y, z] {
+        h = mix(h ^ v as u32 as u64);
+    }
+    h
+}
+
+// Small random number generator (splitmix64), seeded per position
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: seed,
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        mix(self.state)
+    }
+
+    // uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
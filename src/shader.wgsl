@@ -9,12 +9,14 @@ struct VertexInput {
     @location(1) tex_coords: vec2<f32>,
     @location(2) normal: vec3<f32>,
     @location(3) ao: f32,
+    @location(4) layer: u32,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
     @location(1) ao: f32,
+    @location(2) @interpolate(flat) layer: u32,
 };
 
 @vertex
@@ -25,11 +27,12 @@ fn vs_main(
     out.tex_coords = model.tex_coords;
     out.clip_position = globals.view_proj * vec4<f32>(model.position, 1.0);
     out.ao = model.ao;
+    out.layer = model.layer;
     return out;
 }
 
 @group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
+var t_diffuse: texture_2d_array<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
@@ -37,7 +40,7 @@ var s_diffuse: sampler;
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     /*
     var output: vec4<f32>;
-    var tout = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    var tout = textureSample(t_diffuse, s_diffuse, in.tex_coords, in.layer);
     if in.ao > 0.0 {
         output = vec4<f32>(0.5, 0.5, 0.5, 1.0);
     } else {
@@ -45,5 +48,10 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     }
     return output;
     */
-    return mix(textureSample(t_diffuse, s_diffuse, in.tex_coords), vec4<f32>(0.0, 0.0, 0.0, 1.0), in.ao * 0.3);
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords, in.layer);
+    // cut out the see-through parts of plants and such, water is never this clear
+    if color.a < 0.1 {
+        discard;
+    }
+    return mix(color, vec4<f32>(0.0, 0.0, 0.0, 1.0), in.ao * 0.3);
 }
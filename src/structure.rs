@@ -0,0 +1,69 @@
+use crate::block::BlockType;
+use crate::chunk::CHUNK_SIZE;
+use crate::coords::{ChunkPos, WorldPos};
+use nalgebra::{Vector3, vector};
+
+// Blocks placed around an anchor during generation, like trees. A structure may reach into the
+// chunks next to its anchor's, every chunk it reaches writes its own part of it.
+pub struct Structure {
+    pub blocks: Vec<(Vector3<i32>, BlockType)>, // offset from the anchor, earlier blocks win
+}
+
+impl Structure {
+    // trunk on top of the anchor, with leaves around its top
+    pub fn tree() -> Self {
+        let mut blocks = Vec::new();
+        for y in 1..6 {
+            blocks.push((vector![0, y, 0], BlockType::WOOD));
+        }
+        for y in 4..6 {
+            for x in -2..=2 {
+                for z in -2..=2 {
+                    blocks.push((vector![x, y, z], BlockType::LEAVES));
+                }
+            }
+        }
+        for x in -1..=1 {
+            for z in -1..=1 {
+                blocks.push((vector![x, 6, z], BlockType::LEAVES));
+            }
+        }
+        Self::new(blocks)
+    }
+
+    pub fn new(blocks: Vec<(Vector3<i32>, BlockType)>) -> Self {
+        assert!(
+            blocks.iter().all(|(offset, _)| offset.iter().all(|v| v.unsigned_abs() < CHUNK_SIZE as u32)),
+            "structures reach at most into the chunks next to their anchor's",
+        );
+        Self {
+            blocks,
+        }
+    }
+
+    // how far blocks are from the anchor, along x or z, and below and above it
+    pub fn reach(&self) -> i32 {
+        self.blocks.iter().map(|(offset, _)| offset.x.abs().max(offset.z.abs())).max().unwrap_or(0)
+    }
+
+    pub fn height(&self) -> (i32, i32) {
+        let ys = self.blocks.iter().map(|(offset, _)| offset.y);
+        (ys.clone().min().unwrap_or(0), ys.max().unwrap_or(0))
+    }
+
+    // writes the blocks inside the chunk, they only replace blocks without collision that aren't
+    // fluids, like air and plants
+    pub fn place(&self, anchor: WorldPos, chunk_pos: ChunkPos, blocks: &mut [BlockType]) {
+        for &(offset, block) in &self.blocks {
+            let pos = anchor + offset;
+            if pos.chunk() != chunk_pos {
+                continue;
+            }
+            let existing = &mut blocks[pos.local().index()];
+            let properties = existing.properties();
+            if !properties.collision && !properties.fluid {
+                *existing = block;
+            }
+        }
+    }
+}
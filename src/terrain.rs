@@ -1,262 +1,275 @@
-use crate::block::{BlockType, BlockFace};
-use crate::chunk::{Chunk, CHUNK_SIZE};
+use crate::block::{self, BlockType, BlockFace, BlockState};
+use crate::chunk::{Chunk, CHUNK_SIZE, CHUNK_VOLUME};
 use crate::mesh::{Mesh, CMesh, MeshVertex};
+use crate::region::RegionStore;
+use crate::chunk_cache::ChunkCache;
+use crate::coords::{WorldPos, ChunkPos, LocalPos};
+use crate::model::{BlockModel, ModelQuad};
+use crate::tick::{Ticks, RANDOM_TICKS_PER_CHUNK};
+use crate::fluid;
+use crate::falling::{self, FallingBlock};
+use crate::block_entity::BlockEntity;
+use crate::worldgen::WorldGenerator;
 use nalgebra::{Vector3, vector};
 use rayon::ThreadPool;
-use noise::{NoiseFn, Perlin, Curve};
 use rand::Rng;
 use std::{
-    collections::{HashMap, VecDeque},
-    sync::mpsc,
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{mpsc, Arc},
+    time::Duration,
 };
 
 const RENDER_DISTANCE: i32 = 8;
 
 pub struct ChunkGenResponse {
-    position: Vector3<i32>,
+    position: ChunkPos,
     chunk: Chunk,
-    is_empty: bool,
+    edited: bool,
 }
 
-// function used by worker threads
-pub fn gen_chunk(chunk_pos: Vector3<i32>) -> ChunkGenResponse {
-    let mut rng = rand::thread_rng();
-    let perlin = Perlin::new(134);
-    let mut continental_noise: Curve<f64, Perlin, 2> = Curve::new(perlin);
-    continental_noise = continental_noise.add_control_point(-1.01, 50.0);
-    continental_noise = continental_noise.add_control_point(-1.0, 0.0);
-    continental_noise = continental_noise.add_control_point(-0.2, 50.0);
-    continental_noise = continental_noise.add_control_point(0.2, 60.0);
-    continental_noise = continental_noise.add_control_point(0.6, 60.0);
-    continental_noise = continental_noise.add_control_point(1.0, 150.0);
-    continental_noise = continental_noise.add_control_point(1.01, 100.0);
-    let mut chunk = Chunk::new();
-
-    let mut blocks: [BlockType; CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE]
-        = [BlockType::Air; CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE];
-    for i in 0..blocks.len() {
-        let x = i % CHUNK_SIZE;
-        let y = (i / CHUNK_SIZE) % CHUNK_SIZE;
-        let z = i / (CHUNK_SIZE*CHUNK_SIZE);
-        let px = ((chunk_pos.x * CHUNK_SIZE as i32) + x as i32) as f64;
-        let pz = ((chunk_pos.z * CHUNK_SIZE as i32) + z as i32) as f64;
-        let continental = &continental_noise.get([
-            px / 320.0,
-            pz / 320.0,
-        ]);
-        let nv1 = perlin.get([
-            px / 160.0,
-            pz / 160.0,
-        ]) * 16.0;
-        let nv2 = perlin.get([
-            px / 80.0,
-            pz / 80.0,
-        ]) * 16.0;
-        let nv3 = perlin.get([
-            px / 40.0,
-            pz / 40.0,
-        ]) * 16.0;
-        let terrain_height = continental + nv1 + 0.5*nv2 + 0.25*nv3;
-        let block_height = ((chunk_pos.y * CHUNK_SIZE as i32) + y as i32) as f64;
-        if terrain_height > block_height {
-            blocks[i] = BlockType::Stone;
-        }
-    }
-
-    for i in 0..blocks.len() {
-        match blocks[i] {
-            BlockType::Air => {
-                let y = (i / CHUNK_SIZE) % CHUNK_SIZE;
-                let block_height = ((chunk_pos.y * CHUNK_SIZE as i32) + y as i32) as f64;
-                if block_height <= 40.0 {
-                    //water goes here
-                    blocks[i] = BlockType::Water;
-                }
+// function used by worker threads, saved chunks take priority over generating new ones
+pub fn load_chunk(region_store: Option<&RegionStore>, generator: &dyn WorldGenerator, chunk_pos: ChunkPos) -> ChunkGenResponse {
+    if let Some(region_store) = region_store {
+        match region_store.load_chunk(chunk_pos) {
+            // only chunks with edits are ever saved
+            Ok(Some(chunk)) => return ChunkGenResponse {
+                position: chunk_pos,
+                chunk,
+                edited: true,
             },
-            _ => {},
-        }
-    }
-    for x in 0..CHUNK_SIZE {
-        for z in 0..CHUNK_SIZE {
-            let mut max_y = 0;
-            let mut not_air = false;
-            for y in 0..CHUNK_SIZE {
-                match blocks[x + y*CHUNK_SIZE + z*CHUNK_SIZE*CHUNK_SIZE] {
-                    BlockType::Air => {},
-                    BlockType::Water => {},
-                    _ => {
-                        max_y = y;
-                        not_air = true;
-                    },
-                }
-            }
-            if not_air {
-                let block_height = ((chunk_pos.y * CHUNK_SIZE as i32) + max_y as i32) as f64;
-                if block_height < 42.0 {
-                    blocks[x + max_y*CHUNK_SIZE + z*CHUNK_SIZE*CHUNK_SIZE] = BlockType::Sand;
-                    if max_y >= 2 {
-                        blocks[x + (max_y-1)*CHUNK_SIZE + z*CHUNK_SIZE*CHUNK_SIZE] = BlockType::Sand;
-                        blocks[x + (max_y-2)*CHUNK_SIZE + z*CHUNK_SIZE*CHUNK_SIZE] = BlockType::Sand;
-                    } else if max_y >= 1 {
-                        blocks[x + (max_y-1)*CHUNK_SIZE + z*CHUNK_SIZE*CHUNK_SIZE] = BlockType::Sand;
-                    }
-                } else {
-                    blocks[x + max_y*CHUNK_SIZE + z*CHUNK_SIZE*CHUNK_SIZE] = BlockType::Grass;
-                    if max_y >= 2 {
-                        blocks[x + (max_y-1)*CHUNK_SIZE + z*CHUNK_SIZE*CHUNK_SIZE] = BlockType::Dirt;
-                        blocks[x + (max_y-2)*CHUNK_SIZE + z*CHUNK_SIZE*CHUNK_SIZE] = BlockType::Dirt;
-                    } else if max_y >= 1 {
-                        blocks[x + (max_y-1)*CHUNK_SIZE + z*CHUNK_SIZE*CHUNK_SIZE] = BlockType::Dirt;
-                    }
-                }
-            }
+            Ok(None) => {},
+            Err(e) => eprintln!("failed to load chunk {:?}, regenerating it: {:?}", chunk_pos, e),
         }
     }
-
-    for x in 2..CHUNK_SIZE-2 {
-        for y in 0..CHUNK_SIZE-6 {
-            for z in 2..CHUNK_SIZE-2 {
-                let block = blocks[x + y*CHUNK_SIZE + z*CHUNK_SIZE*CHUNK_SIZE];
-                if block == BlockType::Grass {
-                    let rval = rng.gen_range(0.0..1.0);
-                    if rval > 0.99 {
-                        for tree_height in 1..6 {
-                            if y+tree_height < CHUNK_SIZE {
-                                blocks[x + (y+tree_height)*CHUNK_SIZE + z*CHUNK_SIZE*CHUNK_SIZE] = BlockType::Wood;
-                            }
-                        }
-
-                        for leaf_height in 4..6 {
-                            for lx in -2..=2 {
-                                for lz in -2..=2 {
-                                    if ((x as i32+lx) as usize) < CHUNK_SIZE && (x as i32+lx) as usize >= 0
-                                    && ((z as i32+lz) as usize) < CHUNK_SIZE && (z as i32+lz) as usize >= 0
-                                    && y+leaf_height < CHUNK_SIZE {
-                                        let block_pos = (x as i32+lx) as usize
-                                            + (y+leaf_height)*CHUNK_SIZE
-                                            + (z as i32+lz) as usize*CHUNK_SIZE*CHUNK_SIZE;
-                                        if blocks[block_pos] == BlockType::Air {
-                                            blocks[block_pos] = BlockType::Leaves;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-
-                        for lx in -1..=1 {
-                            for lz in -1..=1 {
-                                    if ((x as i32+lx) as usize) < CHUNK_SIZE && (x as i32+lx) as usize >= 0
-                                    && ((z as i32+lz) as usize) < CHUNK_SIZE && (z as i32+lz) as usize >= 0
-                                    && y+6 < CHUNK_SIZE {
-                                        let block_pos = (x as i32+lx) as usize
-                                            + (y+6)*CHUNK_SIZE
-                                            + (z as i32+lz) as usize*CHUNK_SIZE*CHUNK_SIZE;
-                                        if blocks[block_pos] == BlockType::Air {
-                                            blocks[block_pos] = BlockType::Leaves;
-                                        }
-                                    }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    chunk.set(blocks);
     ChunkGenResponse {
         position: chunk_pos,
-        chunk,
-        is_empty: !blocks.into_iter().any(|b| b != BlockType::Air),
+        chunk: generator.generate(chunk_pos),
+        edited: false,
     }
 }
 
+// position within the chunk, new block and its state
+pub type BlockChange = (LocalPos, BlockType, BlockState);
+
 pub struct TerrainChanges {
-    pub loaded_chunks: Vec<Vector3<i32>>,
-    pub unloaded_chunks: Vec<Vector3<i32>>,
-    pub modified_chunks: HashMap<Vector3<i32>, Vec<(Vector3<usize>, BlockType)>>,
+    pub loaded_chunks: Vec<ChunkPos>,
+    pub unloaded_chunks: Vec<ChunkPos>,
+    pub modified_chunks: HashMap<ChunkPos, Vec<BlockChange>>,
+    // unmodified chunks whose faces or ao depend on blocks modified next to them
+    pub remesh_chunks: HashSet<ChunkPos>,
 }
 
 impl TerrainChanges {
     pub fn new() -> Self {
-        let loaded_chunks: Vec<Vector3<i32>> = Vec::new();
-        let unloaded_chunks: Vec<Vector3<i32>> = Vec::new();
-        let modified_chunks: HashMap<Vector3<i32>, Vec<(Vector3<usize>, BlockType)>> = HashMap::new();
+        let loaded_chunks: Vec<ChunkPos> = Vec::new();
+        let unloaded_chunks: Vec<ChunkPos> = Vec::new();
+        let modified_chunks: HashMap<ChunkPos, Vec<BlockChange>> = HashMap::new();
+        let remesh_chunks: HashSet<ChunkPos> = HashSet::new();
 
         Self {
             loaded_chunks,
             unloaded_chunks,
             modified_chunks,
+            remesh_chunks,
         }
     }
 }
 
+// Batches block edits made through Terrain::edit. Edits apply to the loaded chunks straight away
+// and are reported, together with the chunks that need remeshing, by the next Terrain::update.
+pub struct TerrainEdit<'a> {
+    terrain: &'a mut Terrain,
+}
+
+impl TerrainEdit<'_> {
+    pub fn get_block(&self, block_world_pos: WorldPos) -> Option<(LocalPos, BlockType, BlockState)> {
+        self.terrain.get_block(block_world_pos)
+    }
+
+    // returns false if the block's chunk isn't loaded
+    pub fn set_block(&mut self, block_world_pos: WorldPos, block: BlockType) -> bool {
+        self.set_block_state(block_world_pos, block, BlockState::default())
+    }
+
+    // the chunk gets saved whether or not the entity is changed
+    pub fn block_entity_mut(&mut self, block_world_pos: WorldPos) -> Option<&mut BlockEntity> {
+        let (chunk_pos, block_pos) = block_world_pos.split();
+        let chunk_data = self.terrain.chunk_map.get_mut(&chunk_pos)?;
+        let entity = chunk_data.chunk.get_entity_mut(block_pos)?;
+        chunk_data.dirty = true;
+        chunk_data.edited = true;
+        Some(entity)
+    }
+
+    // turns the block into a falling block, returns false if the block's chunk isn't loaded
+    pub fn start_falling(&mut self, block_world_pos: WorldPos) -> bool {
+        let (block, state) = match self.get_block(block_world_pos) {
+            Some((_, block, state)) => (block, state),
+            None => return false,
+        };
+        self.set_block(block_world_pos, BlockType::AIR);
+        self.terrain.falling_blocks.push(FallingBlock::new(block, state, block_world_pos));
+        true
+    }
+
+    pub fn set_block_state(&mut self, block_world_pos: WorldPos, block: BlockType, state: BlockState) -> bool {
+        let (chunk_pos, block_pos) = block_world_pos.split();
+        let chunk_data = match self.terrain.chunk_map.get_mut(&chunk_pos) {
+            Some(chunk_data) => chunk_data,
+            None => return false,
+        };
+        // nothing to save or remesh if the block is already there
+        if chunk_data.chunk.get_block(block_pos) == block && chunk_data.chunk.get_state(block_pos) == state {
+            return true;
+        }
+        let replaced = chunk_data.chunk.get_block(block_pos) != block;
+        chunk_data.chunk.set_block(block, block_pos);
+        chunk_data.chunk.set_state(state, block_pos);
+        if replaced {
+            if let Some(kind) = block.properties().entity {
+                chunk_data.chunk.set_entity(Some(kind.create()), block_pos);
+            }
+        }
+        chunk_data.dirty = true;
+        chunk_data.edited = true;
+
+        let changes = &mut self.terrain.pending_changes;
+        changes.modified_chunks.entry(chunk_pos).or_default().push((block_pos, block, state));
+
+        // blocks on the chunk's edges are visible to the neighbors' faces and ao
+        let edge = |p: usize| -> std::ops::RangeInclusive<i32> {
+            if p == 0 {
+                -1..=0
+            } else if p == CHUNK_SIZE-1 {
+                0..=1
+            } else {
+                0..=0
+            }
+        };
+        for x in edge(block_pos.x) {
+            for y in edge(block_pos.y) {
+                for z in edge(block_pos.z) {
+                    if x != 0 || y != 0 || z != 0 {
+                        changes.remesh_chunks.insert(chunk_pos + vector![x, y, z]);
+                    }
+                }
+            }
+        }
+
+        // the new block and its neighbors get to react to the change
+        self.terrain.ticks.schedule(block_world_pos, block);
+        for face in BlockFace::iterator() {
+            let neighbor = block_world_pos + face.normal();
+            if let Some((_, neighbor_block, _)) = self.terrain.get_block(neighbor) {
+                self.terrain.ticks.schedule(neighbor, neighbor_block);
+            }
+        }
+        true
+    }
+}
+
 pub struct ChunkData {
     chunk: Chunk,
-    pub is_empty: bool,
+    dirty: bool, // modified since it was last saved
+    edited: bool, // differs from what the generator makes, kept in memory when unloaded
 }
 
 pub struct Terrain {
-    player_chunk: Vector3<i32>,
-    chunk_map: HashMap<Vector3<i32>, ChunkData>,
+    generator: Arc<dyn WorldGenerator>,
+    player_chunk: ChunkPos,
+    chunk_map: HashMap<ChunkPos, ChunkData>,
+    region_store: Option<Arc<RegionStore>>,
     loading_tx: mpsc::Sender<ChunkGenResponse>, // for cloning and handing to worker threads
     loading_rx: mpsc::Receiver<ChunkGenResponse>,
-    load_todo: Vec<Vector3<i32>>,
-    loading: Vec<Vector3<i32>>,
-    unload_todo: Vec<Vector3<i32>>,
+    load_todo: Vec<ChunkPos>,
+    loading: Vec<ChunkPos>,
+    unload_todo: Vec<ChunkPos>,
+    saved_tx: mpsc::Sender<Vec<ChunkPos>>, // for worker threads saving unloaded chunks
+    saved_rx: mpsc::Receiver<Vec<ChunkPos>>,
+    saving: Vec<ChunkPos>, // not loaded again until their save is done
+    chunk_cache: ChunkCache,
+    pending_changes: TerrainChanges, // edits made since the last update
+    ticks: Ticks,
+    falling_blocks: Vec<FallingBlock>, // in memory only, put down where they are on save
 }
 
 impl Terrain {
-    pub fn new() -> Self {
-        let player_chunk = vector![0, 0, 0];
-        let chunk_map: HashMap<Vector3<i32>, ChunkData> = HashMap::new();
+    // edited chunks are kept in memory after unloading until they take up more than
+    // chunk_cache_budget bytes, the rest is generated again
+    pub fn new(generator: Arc<dyn WorldGenerator>, region_store: Option<RegionStore>, chunk_cache_budget: usize) -> Self {
+        let player_chunk = ChunkPos::new(0, 0, 0);
+        let chunk_map: HashMap<ChunkPos, ChunkData> = HashMap::new();
+        let region_store = region_store.map(Arc::new);
         let (loading_tx, loading_rx) = mpsc::channel();
-        let load_todo: Vec<Vector3<i32>> = Vec::new();
-        let loading: Vec<Vector3<i32>> = Vec::new();
-        let unload_todo: Vec<Vector3<i32>> = Vec::new();
+        let load_todo: Vec<ChunkPos> = Vec::new();
+        let loading: Vec<ChunkPos> = Vec::new();
+        let unload_todo: Vec<ChunkPos> = Vec::new();
+        let (saved_tx, saved_rx) = mpsc::channel();
+        let saving: Vec<ChunkPos> = Vec::new();
+        let chunk_cache = ChunkCache::new(chunk_cache_budget);
+        let pending_changes = TerrainChanges::new();
+        let ticks = Ticks::new();
+        let falling_blocks = Vec::new();
 
         Self {
+            generator,
             player_chunk,
             chunk_map,
+            region_store,
             loading_tx,
             loading_rx,
             load_todo,
             loading,
             unload_todo,
+            saved_tx,
+            saved_rx,
+            saving,
+            chunk_cache,
+            pending_changes,
+            ticks,
+            falling_blocks,
         }
     }
 
-    pub fn get_chunk(&self, chunk_pos: Vector3<i32>) -> Option<&ChunkData> {
+    pub fn get_chunk(&self, chunk_pos: ChunkPos) -> Option<&ChunkData> {
         self.chunk_map.get(&chunk_pos)
     }
 
-    pub fn get_block(&self, block_world_pos: Vector3<i32>) -> Option<(Vector3<usize>, BlockType)> {
-        let block_pos = vector![
-            ((block_world_pos.x % CHUNK_SIZE as i32) + CHUNK_SIZE as i32) % CHUNK_SIZE as i32,
-            ((block_world_pos.y % CHUNK_SIZE as i32) + CHUNK_SIZE as i32) % CHUNK_SIZE as i32,
-            ((block_world_pos.z % CHUNK_SIZE as i32) + CHUNK_SIZE as i32) % CHUNK_SIZE as i32,
-        ];
-        let chunk_pos = (block_world_pos - block_pos) / CHUNK_SIZE as i32;
-        if let Some(chunk_data) = self.get_chunk(chunk_pos) {
-            Some((
-                block_pos.try_cast::<usize>().unwrap(),
-                chunk_data.chunk.get_block(block_pos.try_cast::<usize>().unwrap()),
-            ))
-        } else {
-            None
-        }
+    pub fn get_block(&self, block_world_pos: WorldPos) -> Option<(LocalPos, BlockType, BlockState)> {
+        let (chunk_pos, block_pos) = block_world_pos.split();
+        self.get_chunk(chunk_pos).map(|chunk_data| (
+            block_pos,
+            chunk_data.chunk.get_block(block_pos),
+            chunk_data.chunk.get_state(block_pos),
+        ))
+    }
+
+    pub fn block_entity(&self, block_world_pos: WorldPos) -> Option<&BlockEntity> {
+        let (chunk_pos, block_pos) = block_world_pos.split();
+        self.get_chunk(chunk_pos)?.chunk.get_entity(block_pos)
+    }
+
+    // returns false if the block's chunk isn't loaded
+    pub fn set_block(&mut self, block_world_pos: WorldPos, block: BlockType) -> bool {
+        self.edit(|e| e.set_block(block_world_pos, block))
+    }
+
+    pub fn set_block_state(&mut self, block_world_pos: WorldPos, block: BlockType, state: BlockState) -> bool {
+        self.edit(|e| e.set_block_state(block_world_pos, block, state))
     }
 
-    pub fn check_neighbors(&self, chunk_pos: Vector3<i32>) -> bool {
+    pub fn edit<R>(&mut self, f: impl FnOnce(&mut TerrainEdit) -> R) -> R {
+        f(&mut TerrainEdit {
+            terrain: self,
+        })
+    }
+
+    pub fn check_neighbors(&self, chunk_pos: ChunkPos) -> bool {
         let mut result = true;
         for x in -1..=1 {
             for y in -1..=1 {
                 for z in -1..=1 {
-                    if !self.chunk_map.contains_key(&vector![
-                        chunk_pos.x + x,
-                        chunk_pos.y + y,
-                        chunk_pos.z + z,
-                    ]) {
+                    if !self.chunk_map.contains_key(&(chunk_pos + vector![x, y, z])) {
                         result = false;
                     }
                 }
@@ -265,8 +278,39 @@ impl Terrain {
         result
     }
 
-    pub fn add_chunk(&mut self, chunk_pos: Vector3<i32>, chunk: ChunkData) {
-        if let Some(_) = self.chunk_map.insert(chunk_pos, chunk) {
+    // whether meshing the chunk could produce any faces. Uniform chunks only have faces where
+    // a face neighbor shows through, so if those are uniform and hide it there is nothing to mesh
+    pub fn needs_mesh(&self, chunk_pos: ChunkPos) -> bool {
+        let block = match self.chunk_map.get(&chunk_pos) {
+            Some(chunk_data) => match chunk_data.chunk.uniform_block() {
+                Some(block) => block,
+                None => return true,
+            },
+            None => return false,
+        };
+        let properties = block.properties();
+        if !properties.visible() {
+            return false;
+        }
+        if properties.model != BlockModel::Cube {
+            return true;
+        }
+
+        for offset in [
+            vector![1, 0, 0], vector![-1, 0, 0],
+            vector![0, 1, 0], vector![0, -1, 0],
+            vector![0, 0, 1], vector![0, 0, -1],
+        ] {
+            match self.chunk_map.get(&(chunk_pos + offset)).and_then(|n| n.chunk.uniform_block()) {
+                Some(neighbor) if neighbor.opaque() || neighbor == block => {},
+                _ => return true,
+            }
+        }
+        false
+    }
+
+    pub fn add_chunk(&mut self, chunk_pos: ChunkPos, chunk: ChunkData) {
+        if self.chunk_map.insert(chunk_pos, chunk).is_some() {
             // we just overwrote another chunk, no reason this should be able to happen currently
             eprintln!["uh oh, a chunk was overwritten by another"];
         }
@@ -274,13 +318,44 @@ impl Terrain {
     }
 
     // unload chunk
-    pub fn remove_chunk(&mut self, chunk_pos: Vector3<i32>) {
-        self.chunk_map.remove(&chunk_pos);
+    pub fn remove_chunk(&mut self, chunk_pos: ChunkPos) -> Option<ChunkData> {
         self.load_todo.retain(|chunk| *chunk != chunk_pos);
+        self.chunk_map.remove(&chunk_pos)
+    }
+
+    fn save_chunks(&self, chunks: &[(ChunkPos, &Chunk)]) {
+        if let Some(region_store) = &self.region_store {
+            if let Err(e) = region_store.save_chunks(chunks) {
+                eprintln!("failed to save chunks: {:?}", e);
+            }
+        }
+    }
+
+    // write every modified chunk still loaded back to disk, used on exit. Waits for the saves of
+    // unloaded chunks still running on worker threads too.
+    pub fn save(&mut self) {
+        // blocks still falling are put down where they are, or the first free spot above
+        let mut falling_blocks = std::mem::take(&mut self.falling_blocks);
+        falling_blocks.sort_by(|a, b| a.position.y.total_cmp(&b.position.y));
+        self.edit(|e| for falling_block in &falling_blocks {
+            falling_block.land(e, WorldPos::containing(falling_block.position));
+        });
+        while !self.saving.is_empty() {
+            let saved = self.saved_rx.recv().unwrap();
+            self.saving.retain(|chunk_pos| !saved.contains(chunk_pos));
+        }
+        let dirty: Vec<(ChunkPos, &Chunk)> = self.chunk_map.iter()
+            .filter(|(_, chunk_data)| chunk_data.dirty)
+            .map(|(chunk_pos, chunk_data)| (*chunk_pos, &chunk_data.chunk))
+            .collect();
+        self.save_chunks(&dirty);
+        for chunk_data in self.chunk_map.values_mut() {
+            chunk_data.dirty = false;
+        }
     }
 
     // upon entering new chunk, add list of new chunks to load todo
-    pub fn load_chunks(&mut self, chunk_pos: Vector3<i32>) {
+    pub fn load_chunks(&mut self, chunk_pos: ChunkPos) {
         if !self.chunk_map.contains_key(&chunk_pos)
         && !self.load_todo.contains(&chunk_pos) 
         && !self.loading.contains(&chunk_pos) {
@@ -325,31 +400,79 @@ impl Terrain {
     }
 
     // upon entering new chunk, remove all chunks that are too far from player
-    pub fn unload_chunks(&mut self, chunk_pos: Vector3<i32>) {
-        let mut unload_chunks: Vec<Vector3<i32>> = self.chunk_map.keys().cloned().collect();
-        unload_chunks.retain(|cpos| {
-            (cpos.x - chunk_pos.x).abs() > RENDER_DISTANCE+1
-            || (cpos.y - chunk_pos.y).abs() > RENDER_DISTANCE+1
-            || (cpos.z - chunk_pos.z).abs() > RENDER_DISTANCE+1
-        });
+    pub fn unload_chunks(&mut self, chunk_pos: ChunkPos) {
+        let mut unload_chunks: Vec<ChunkPos> = self.chunk_map.keys().cloned().collect();
+        unload_chunks.retain(|cpos| cpos.max_distance(chunk_pos) > RENDER_DISTANCE+1);
 
-        for chunk in unload_chunks {
-            self.unload_todo.push(chunk);
-        }
+        // replaces the old list, chunks queued from an earlier position may be back in range
+        self.unload_todo = unload_chunks;
     }
 
-    pub fn update(&mut self, player_pos: Vector3<i32>, terrain_changes_in: TerrainChanges, thread_pool: &ThreadPool) -> TerrainChanges {
-        let mut terrain_changes_out = TerrainChanges::new();
+    // runs due scheduled updates, then random ticks in every loaded chunk
+    fn tick(&mut self) {
+        for block_world_pos in self.ticks.next_tick() {
+            if let Some((_, block, _)) = self.get_block(block_world_pos) {
+                if let Some(behavior) = self.ticks.scheduled_behavior(block) {
+                    self.edit(|e| behavior(e, block_world_pos));
+                }
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut picked = Vec::new();
+        for (chunk_pos, chunk_data) in &self.chunk_map {
+            // most chunks are all air or stone
+            if let Some(block) = chunk_data.chunk.uniform_block() {
+                if self.ticks.random_behavior(block).is_none() {
+                    continue;
+                }
+            }
+            for _ in 0..RANDOM_TICKS_PER_CHUNK {
+                let block_pos = LocalPos::from_index(rng.gen_range(0..CHUNK_VOLUME));
+                if self.ticks.random_behavior(chunk_data.chunk.get_block(block_pos)).is_some() {
+                    picked.push(chunk_pos.world_pos(block_pos));
+                }
+            }
+        }
+        // earlier behaviors may have changed the block
+        for block_world_pos in picked {
+            if let Some((_, block, _)) = self.get_block(block_world_pos) {
+                if let Some(behavior) = self.ticks.random_behavior(block) {
+                    self.edit(|e| behavior(e, block_world_pos));
+                }
+            }
+        }
 
-        for (chunk_pos, block_changes) in &terrain_changes_in.modified_chunks {
-            let mut chunk_data = self.chunk_map.get_mut(&chunk_pos).unwrap();
-            for (block_pos, new_block) in block_changes {
-                chunk_data.chunk.set_block(*new_block, *block_pos);
+        // lowest first, so blocks falling on top of each other land in order
+        let mut falling_blocks = std::mem::take(&mut self.falling_blocks);
+        falling_blocks.sort_by(|a, b| a.position.y.total_cmp(&b.position.y));
+        self.edit(|e| falling_blocks.retain_mut(|falling_block| {
+            let landed = match falling_block.step(e) {
+                Some(landed) => landed,
+                None => return true,
+            };
+            match falling_block.land(e, landed) {
+                // waits above until the chunk is loaded
+                Some(waiting) => {
+                    falling_block.position = waiting.center();
+                    falling_block.previous = falling_block.position;
+                    true
+                },
+                None => false,
             }
-            chunk_data.is_empty = !chunk_data.chunk.blocks.into_iter().any(|b| b != BlockType::Air);
-            terrain_changes_out.modified_chunks.insert(*chunk_pos, block_changes.to_vec());
+        }));
+        falling_blocks.append(&mut self.falling_blocks);
+        self.falling_blocks = falling_blocks;
+    }
+
+    // dt is the time since the last update, block ticks run at a fixed rate
+    pub fn update(&mut self, player_pos: ChunkPos, dt: Duration, thread_pool: &ThreadPool) -> TerrainChanges {
+        for _ in 0..self.ticks.advance(dt) {
+            self.tick();
         }
 
+        let mut terrain_changes_out = std::mem::replace(&mut self.pending_changes, TerrainChanges::new());
+
         if player_pos != self.player_chunk ||
             (self.chunk_map.is_empty() && self.load_todo.is_empty() && self.loading.is_empty()) {
             self.load_chunks(player_pos);
@@ -357,11 +480,31 @@ impl Terrain {
             self.player_chunk = player_pos;
         }
 
-        for chunk in self.load_todo.drain(..) {
-            let tchunk = chunk.clone();
+        for saved in self.saved_rx.try_iter() {
+            self.saving.retain(|chunk_pos| !saved.contains(chunk_pos));
+        }
+
+        for chunk in std::mem::take(&mut self.load_todo) {
+            if let Some(cached) = self.chunk_cache.take(chunk) {
+                self.add_chunk(chunk, ChunkData {
+                    chunk: cached,
+                    dirty: false,
+                    edited: true,
+                });
+                terrain_changes_out.loaded_chunks.push(chunk);
+                continue;
+            }
+            // the region file doesn't have its edits yet
+            if self.saving.contains(&chunk) {
+                self.load_todo.push(chunk);
+                continue;
+            }
+            let tchunk = chunk;
             let loading_tx = self.loading_tx.clone();
+            let region_store = self.region_store.clone();
+            let generator = self.generator.clone();
             thread_pool.spawn(move || {
-                let _ = loading_tx.send(gen_chunk(tchunk));
+                let _ = loading_tx.send(load_chunk(region_store.as_deref(), generator.as_ref(), tchunk));
             });
             self.loading.push(chunk);
         }
@@ -370,31 +513,95 @@ impl Terrain {
         for response in to_add {
             self.add_chunk(response.position, ChunkData {
                 chunk: response.chunk,
-                is_empty: response.is_empty,
+                dirty: false,
+                edited: response.edited,
             });
             self.loading.retain(|c| *c != response.position);
             terrain_changes_out.loaded_chunks.push(response.position);
         }
 
+        let mut unloaded_edited = Vec::new();
         for _ in 0..10 {
             if let Some(chunk) = self.unload_todo.pop() {
-                self.remove_chunk(chunk);
+                if let Some(chunk_data) = self.remove_chunk(chunk) {
+                    if chunk_data.edited {
+                        unloaded_edited.push((chunk, chunk_data));
+                    }
+                }
                 terrain_changes_out.unloaded_chunks.push(chunk);
             }
         }
-
+        // saved together on a worker thread, so every region is written at most once per update
+        let to_save: Vec<(ChunkPos, Chunk)> = unloaded_edited.iter()
+            .filter(|(_, chunk_data)| chunk_data.dirty)
+            .map(|(chunk_pos, chunk_data)| (*chunk_pos, chunk_data.chunk.clone()))
+            .collect();
+        if let Some(region_store) = self.region_store.clone().filter(|_| !to_save.is_empty()) {
+            let saved_tx = self.saved_tx.clone();
+            self.saving.extend(to_save.iter().map(|(chunk_pos, _)| *chunk_pos));
+            thread_pool.spawn(move || {
+                let chunks: Vec<(ChunkPos, &Chunk)> = to_save.iter().map(|(chunk_pos, chunk)| (*chunk_pos, chunk)).collect();
+                if let Err(e) = region_store.save_chunks(&chunks) {
+                    eprintln!("failed to save chunks: {:?}", e);
+                }
+                let _ = saved_tx.send(to_save.into_iter().map(|(chunk_pos, _)| chunk_pos).collect());
+            });
+        }
+        for (chunk_pos, chunk_data) in unloaded_edited {
+            self.chunk_cache.insert(chunk_pos, chunk_data.chunk);
+        }
 
         terrain_changes_out
     }
 }
 
+// Terrain driven by hand, for testing what runs on it without worker threads or a player
+#[cfg(test)]
+impl Terrain {
+    // the chunks are generated straight away, the rest of the world is unloaded
+    pub fn with_chunks(generator: Arc<dyn WorldGenerator>, chunks: impl IntoIterator<Item = ChunkPos>) -> Self {
+        let mut terrain = Self::new(generator, None, 0);
+        for chunk_pos in chunks {
+            let response = load_chunk(None, terrain.generator.as_ref(), chunk_pos);
+            terrain.add_chunk(chunk_pos, ChunkData {
+                chunk: response.chunk,
+                dirty: false,
+                edited: false,
+            });
+        }
+        terrain
+    }
+
+    // stone below y height and air above, in the two chunks above the origin
+    pub fn flat(height: u32) -> Self {
+        let chunks = [ChunkPos::new(0, 0, 0), ChunkPos::new(0, 1, 0)];
+        let generator = crate::worldgen::FlatGenerator::new(vec![(BlockType::STONE, height)]);
+        Self::with_chunks(Arc::new(generator), chunks)
+    }
+
+    pub fn run_ticks(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    // the edits made since the last call, as update would report them
+    pub fn take_changes(&mut self) -> TerrainChanges {
+        std::mem::replace(&mut self.pending_changes, TerrainChanges::new())
+    }
+
+    pub fn falling_blocks(&self) -> impl Iterator<Item = &FallingBlock> {
+        self.falling_blocks.iter()
+    }
+}
+
 pub struct ChunkMeshResponse {
     opaque_mesh: CMesh,
     transparent_mesh: CMesh,
 }
 
 // function used by worker threads
-pub fn mesh_chunk(chunk_pos: Vector3<i32>, chunk: Chunk, neighbors: &[Chunk]) -> ChunkMeshResponse {
+pub fn mesh_chunk(chunk_pos: ChunkPos, chunk: Chunk, neighbors: &[Chunk]) -> ChunkMeshResponse {
     let mut opaque_chunk_vertices: Vec<MeshVertex> = Vec::new();
     let mut opaque_chunk_indices: Vec<u32> = Vec::new();
     let mut oo: u32 = 0;
@@ -402,28 +609,93 @@ pub fn mesh_chunk(chunk_pos: Vector3<i32>, chunk: Chunk, neighbors: &[Chunk]) ->
     let mut transparent_chunk_indices: Vec<u32> = Vec::new();
     let mut to: u32 = 0;
 
-    for (i, block) in chunk.blocks.into_iter().enumerate() {
-        if block.opaque() || block.transparent() {
-            for face in BlockFace::iterator() {
-                let block_pos = vector![
-                    i % CHUNK_SIZE,
-                    (i / CHUNK_SIZE) % CHUNK_SIZE,
-                    i / (CHUNK_SIZE*CHUNK_SIZE),
-                ];
-                let mut n = block_pos.cast::<i32>();
-                match face {
-                    BlockFace::Top => n.y += 1,
-                    BlockFace::Bottom => n.y -= 1,
-                    BlockFace::Front => n.z += 1,
-                    BlockFace::Back => n.z -= 1,
-                    BlockFace::Right => n.x += 1,
-                    BlockFace::Left => n.x -= 1,
+    let registry = block::registry();
+    for (i, block) in chunk.iter().enumerate() {
+        let properties = registry.get(block);
+        let block_pos = LocalPos::from_index(i);
+        let neighbor = |face: &BlockFace| chunk.get_block_border(neighbors, block_pos.vector() + face.normal());
+        if properties.model != BlockModel::Cube {
+            let center = chunk_pos.world_pos(block_pos).center();
+            let connects = |face| {
+                let neighbor = neighbor(&face);
+                neighbor == block || registry.get(neighbor).opaque
+            };
+            // flush quads are hidden by opaque neighbors, and by the quads of neighboring models
+            // that cover them, like the sides of two slabs next to each other
+            let hidden = |quad: &ModelQuad, face: BlockFace| {
+                let neighbor_pos = block_pos.vector() + face.normal();
+                let neighbor = chunk.get_block_border(neighbors, neighbor_pos);
+                let neighbor_properties = registry.get(neighbor);
+                if neighbor_properties.opaque {
+                    return true;
+                }
+                if neighbor != block && neighbor_properties.transparent {
+                    return false;
+                }
+                // only the neighbor's rail towards this block could be flush with this side
+                let neighbor_connects = |f: BlockFace| f == face.opposite() && neighbor == block;
+                neighbor_properties.model
+                    .quads(chunk.get_state_border(neighbors, neighbor_pos), neighbor_connects)
+                    .iter()
+                    .any(|neighbor_quad| neighbor_quad.covers(quad))
+            };
+            for quad in properties.model.quads(chunk.get_state(block_pos), connects) {
+                if quad.cull_face.is_some_and(|face| hidden(&quad, face)) {
+                    continue;
                 }
-                let neighbor = chunk.get_block_border(neighbors, n);
-                if !neighbor.opaque() && neighbor != block {
-                    if block.opaque() {
+                let layer = properties.textures[quad.texture_face as usize];
+                let vertices = quad.positions.iter().zip(quad.tex_coords).map(|(p, tex_coords)| MeshVertex {
+                    position: (center + p).into(),
+                    tex_coords,
+                    normal: quad.normal,
+                    ao: 0.0,
+                    layer,
+                });
+                if properties.transparent {
+                    transparent_chunk_vertices.extend(vertices);
+                    transparent_chunk_indices.extend_from_slice(&[to,to+2,to+1,to+2,to+3,to+1]);
+                    to += 4;
+                } else {
+                    opaque_chunk_vertices.extend(vertices);
+                    opaque_chunk_indices.extend_from_slice(&[oo,oo+2,oo+1,oo+2,oo+3,oo+1]);
+                    oo += 4;
+                }
+            }
+        } else if properties.opaque || properties.transparent {
+            // fluid surfaces sit lower the further they have flowed, so faces may not reach the top
+            // of the block, and sides only show above lower neighbors of the same fluid
+            let surface = |position: Vector3<i32>| fluid::surface_height(
+                chunk.get_state_border(neighbors, position).level(),
+                chunk.get_block_border(neighbors, position + BlockFace::Top.normal()) == block,
+            ) - 0.5;
+            let top = if properties.fluid { surface(block_pos.vector()) } else { 0.5 };
+            for face in BlockFace::iterator() {
+                let neighbor = neighbor(face);
+                let mut bottom = -0.5;
+                let visible = if registry.get(neighbor).opaque {
+                    *face == BlockFace::Top && top < 0.5
+                } else if neighbor == block {
+                    if properties.fluid && face.axis() != 1 {
+                        bottom = surface(block_pos.vector() + face.normal());
+                        bottom < top
+                    } else {
+                        false
+                    }
+                } else {
+                    true
+                };
+                if visible {
+                    let layer = properties.texture(face, chunk.get_state(block_pos));
+                    let center = chunk_pos.world_pos(block_pos).center();
+                    let shape = |v: &MeshVertex| {
+                        let mut p = Vector3::from(v.position);
+                        p.y = if p.y > 0.0 { top } else { p.y.max(bottom) };
+                        let tex_coords = if properties.fluid { face.uv(p) } else { v.tex_coords };
+                        ((center + p).into(), tex_coords)
+                    };
+                    if properties.opaque {
                         opaque_chunk_vertices.extend(
-                            face.get_vertices().into_iter().map(|v| {
+                            face.get_vertices().iter().map(|v| {
                                 let mut ao = 0.0;
                                 let n1 = vector![
                                     block_pos.x as i32 + (v.position[0] * 2.0) as i32,
@@ -442,65 +714,45 @@ pub fn mesh_chunk(chunk_pos: Vector3<i32>, chunk: Chunk, neighbors: &[Chunk]) ->
                                 ];
 
                                 let mut cv = false;
-                                if chunk.get_block_border(neighbors, n1).opaque() {
+                                if registry.get(chunk.get_block_border(neighbors, n1)).opaque {
                                     ao += 1.0;
                                     cv = true;
                                 }
-                                if chunk.get_block_border(neighbors, n2).opaque() {
+                                if registry.get(chunk.get_block_border(neighbors, n2)).opaque {
                                     ao += 1.0;
                                     cv = true;
                                 }
-                                if cv && chunk.get_block_border(neighbors, n3).opaque() {
+                                if cv && registry.get(chunk.get_block_border(neighbors, n3)).opaque {
                                     ao += 1.0;
                                 }
                                 
 
+                                let (position, tex_coords) = shape(v);
                                 MeshVertex {
-                                    position: [
-                                        (chunk_pos.x * CHUNK_SIZE as i32) as f32
-                                            + v.position[0] + (i % CHUNK_SIZE) as f32,
-                                        (chunk_pos.y * CHUNK_SIZE as i32) as f32
-                                            + v.position[1] + ((i / CHUNK_SIZE) % CHUNK_SIZE) as f32,
-                                        (chunk_pos.z * CHUNK_SIZE as i32) as f32
-                                            + v.position[2] + (i / (CHUNK_SIZE*CHUNK_SIZE)) as f32,
-                                    ],
-                                    tex_coords: [
-                                        (block.texture(face) % 16) as f32 * 0.0625
-                                            + (v.tex_coords[0] * 0.0625),
-                                        (block.texture(face) / 16) as f32 * 0.0625
-                                            + (v.tex_coords[1] * 0.0625),
-                                    ],
+                                    position,
+                                    tex_coords,
                                     normal: v.normal,
                                     ao,
+                                    layer,
                                 }
                             })
                         );
-                        opaque_chunk_indices.extend_from_slice(&[oo+0,oo+2,oo+1,oo+2,oo+3,oo+1]);
+                        opaque_chunk_indices.extend_from_slice(&[oo,oo+2,oo+1,oo+2,oo+3,oo+1]);
                         oo += 4;
-                    } else if block.transparent() {
+                    } else if properties.transparent {
                         transparent_chunk_vertices.extend(
-                            face.get_vertices().into_iter().map(|v| {
+                            face.get_vertices().iter().map(|v| {
+                                let (position, tex_coords) = shape(v);
                                 MeshVertex {
-                                    position: [
-                                        (chunk_pos.x * CHUNK_SIZE as i32) as f32
-                                            + v.position[0] + (i % CHUNK_SIZE) as f32,
-                                        (chunk_pos.y * CHUNK_SIZE as i32) as f32
-                                            + v.position[1] + ((i / CHUNK_SIZE) % CHUNK_SIZE) as f32,
-                                        (chunk_pos.z * CHUNK_SIZE as i32) as f32
-                                            + v.position[2] + (i / (CHUNK_SIZE*CHUNK_SIZE)) as f32,
-                                    ],
-                                    tex_coords: [
-                                        (block.texture(face) % 16) as f32 * 0.0625
-                                            + (v.tex_coords[0] * 0.0625),
-                                        (block.texture(face) / 16) as f32 * 0.0625
-                                            + (v.tex_coords[1] * 0.0625),
-                                    ],
+                                    position,
+                                    tex_coords,
                                     normal: v.normal,
                                     ao: 0.0,
+                                    layer,
                                 }
                             })
                         );
-                        transparent_chunk_indices.extend_from_slice(&[to+0,to+2,to+1,to+2,to+3,to+1]);
+                        transparent_chunk_indices.extend_from_slice(&[to,to+2,to+1,to+2,to+3,to+1]);
                         to += 4;
                     }
                 }
@@ -515,21 +767,23 @@ pub fn mesh_chunk(chunk_pos: Vector3<i32>, chunk: Chunk, neighbors: &[Chunk]) ->
 }
 
 pub struct TerrainMesh {
-    player_chunk: Vector3<i32>,
-    meshed_chunks: HashMap<Vector3<i32>, Mesh>,
-    meshed_chunks_transparent: HashMap<Vector3<i32>, Mesh>,
-    meshing_tx: mpsc::Sender<(Vector3<i32>, ChunkMeshResponse)>,
-    meshing_rx: mpsc::Receiver<(Vector3<i32>, ChunkMeshResponse)>,
-    meshes_todo: VecDeque<Vector3<i32>>,
+    player_chunk: ChunkPos,
+    meshed_chunks: HashMap<ChunkPos, Mesh>,
+    meshed_chunks_transparent: HashMap<ChunkPos, Mesh>,
+    meshing_tx: mpsc::Sender<(ChunkPos, ChunkMeshResponse)>,
+    meshing_rx: mpsc::Receiver<(ChunkPos, ChunkMeshResponse)>,
+    meshes_todo: VecDeque<ChunkPos>,
+    falling_blocks: Option<Mesh>, // rebuilt every frame
 }
 
 impl TerrainMesh {
     pub fn new() -> Self {
-        let player_chunk = vector![0, 0, 0];
-        let meshed_chunks: HashMap<Vector3<i32>, Mesh> = HashMap::new();
-        let meshed_chunks_transparent: HashMap<Vector3<i32>, Mesh> = HashMap::new();
+        let player_chunk = ChunkPos::new(0, 0, 0);
+        let meshed_chunks: HashMap<ChunkPos, Mesh> = HashMap::new();
+        let meshed_chunks_transparent: HashMap<ChunkPos, Mesh> = HashMap::new();
         let (meshing_tx, meshing_rx) = mpsc::channel();
-        let meshes_todo: VecDeque<Vector3<i32>> = VecDeque::new();
+        let meshes_todo: VecDeque<ChunkPos> = VecDeque::new();
+        let falling_blocks = None;
 
         Self {
             player_chunk,
@@ -538,16 +792,17 @@ impl TerrainMesh {
             meshing_tx,
             meshing_rx,
             meshes_todo,
+            falling_blocks,
         }
     }
 
-    pub fn insert_chunk(&mut self, chunk_pos: Vector3<i32>, mesh: Mesh) {
-        if let Some(_) = self.meshed_chunks.insert(chunk_pos, mesh) {
+    pub fn insert_chunk(&mut self, chunk_pos: ChunkPos, mesh: Mesh) {
+        if self.meshed_chunks.insert(chunk_pos, mesh).is_some() {
             // old mesh rewritten. If I add metadata for meshes, delete it here
         }
     }
 
-    pub fn remove_chunk(&mut self, chunk_pos: Vector3<i32>) {
+    pub fn remove_chunk(&mut self, chunk_pos: ChunkPos) {
         self.meshed_chunks.remove(&chunk_pos);
         self.meshed_chunks_transparent.remove(&chunk_pos);
         self.meshes_todo.retain(|chunk| *chunk != chunk_pos);
@@ -555,9 +810,10 @@ impl TerrainMesh {
 
     pub fn get_opaque_meshes(&self) -> Vec<&Mesh> {
         let mut render_meshes = Vec::new();
-        for (_, mesh) in &self.meshed_chunks {
+        for mesh in self.meshed_chunks.values() {
             render_meshes.push(mesh);
         }
+        render_meshes.extend(&self.falling_blocks);
         render_meshes
     }
 
@@ -567,17 +823,20 @@ impl TerrainMesh {
             sorted_meshes.push(chunk);
         }
         let p_pos = self.player_chunk;
-        sorted_meshes.sort_by(|a, b| (b.0-p_pos).cast::<f32>().norm().partial_cmp(&(a.0-p_pos).cast::<f32>().norm()).unwrap());
+        sorted_meshes.sort_by(|a, b| (b.0.vector()-p_pos.vector()).cast::<f32>().norm().partial_cmp(&(a.0.vector()-p_pos.vector()).cast::<f32>().norm()).unwrap());
         let mut render_meshes = Vec::new();
-        for (p, mesh) in sorted_meshes {
+        for (_, mesh) in sorted_meshes {
             render_meshes.push(mesh);
         }
         render_meshes
     }
 
-    pub fn update(&mut self, terrain_changes: &TerrainChanges, terrain_data: &Terrain, player_pos: Vector3<i32>, device: &wgpu::Device, thread_pool: &ThreadPool) {
+    pub fn update(&mut self, terrain_changes: &TerrainChanges, terrain_data: &Terrain, player_pos: ChunkPos, device: &wgpu::Device, thread_pool: &ThreadPool) {
         self.player_chunk = player_pos;
 
+        self.falling_blocks = falling::mesh(terrain_data.falling_blocks.iter(), terrain_data.ticks.progress())
+            .map(|mesh| Mesh::new(device, &mesh));
+
         for chunk in &terrain_changes.unloaded_chunks {
             self.remove_chunk(*chunk);
 
@@ -585,7 +844,7 @@ impl TerrainMesh {
                 for y in -1..=1 {
                     for z in -1..=1 {
                         if x != 0 || y != 0 || z != 0 {
-                            let n_pos = chunk + vector![x, y, z];
+                            let n_pos = *chunk + vector![x, y, z];
                             self.meshes_todo.retain(|chunk| *chunk != n_pos);
                         }
                     }
@@ -593,60 +852,28 @@ impl TerrainMesh {
             }
         }
 
-        for (chunk, _) in &terrain_changes.modified_chunks {
-            if terrain_data.check_neighbors(*chunk) {
+        // chunks left with nothing to draw still go through the queue so their old mesh is dropped
+        for chunk in terrain_changes.modified_chunks.keys().chain(terrain_changes.remesh_chunks.iter()) {
+            if !self.meshes_todo.contains(chunk) && terrain_data.check_neighbors(*chunk)
+            && chunk.max_distance(self.player_chunk) <= RENDER_DISTANCE {
                 self.meshes_todo.push_front(*chunk);
             }
-
-            for x in -1..=1 {
-                for y in -1..=1 {
-                    for z in -1..=1 {
-                        let n_pos = chunk + vector![x, y, z];
-                        if !self.meshes_todo.contains(&n_pos) {
-                            match terrain_data.chunk_map.get(&n_pos) {
-                                Some(n_data) => {
-                                    if !n_data.is_empty && terrain_data.check_neighbors(n_pos) {
-                                        if (n_pos.x - self.player_chunk.x).abs() <= RENDER_DISTANCE
-                                        && (n_pos.y - self.player_chunk.y).abs() <= RENDER_DISTANCE
-                                        && (n_pos.z - self.player_chunk.z).abs() <= RENDER_DISTANCE {
-                                            self.meshes_todo.push_front(n_pos);
-                                        }
-                                    }
-                                },
-                                None => {},
-                            }
-                        }
-                    }
-                }
-            }
         }
 
         for chunk in &terrain_changes.loaded_chunks {
-            if !terrain_data.chunk_map.get(chunk).unwrap().is_empty && terrain_data.check_neighbors(*chunk) {
-                if (chunk.x - self.player_chunk.x).abs() <= RENDER_DISTANCE
-                && (chunk.y - self.player_chunk.y).abs() <= RENDER_DISTANCE
-                && (chunk.z - self.player_chunk.z).abs() <= RENDER_DISTANCE {
-                    self.meshes_todo.push_back(*chunk);
-                }
+            if terrain_data.needs_mesh(*chunk) && terrain_data.check_neighbors(*chunk)
+            && chunk.max_distance(self.player_chunk) <= RENDER_DISTANCE {
+                self.meshes_todo.push_back(*chunk);
             }
 
             for x in -1..=1 {
                 for y in -1..=1 {
                     for z in -1..=1 {
-                        let n_pos = chunk + vector![x, y, z];
-                        if !self.meshes_todo.contains(&n_pos) {
-                            match terrain_data.chunk_map.get(&n_pos) {
-                                Some(n_data) => {
-                                    if !n_data.is_empty && terrain_data.check_neighbors(n_pos) {
-                                        if (n_pos.x - self.player_chunk.x).abs() <= RENDER_DISTANCE
-                                        && (n_pos.y - self.player_chunk.y).abs() <= RENDER_DISTANCE
-                                        && (n_pos.z - self.player_chunk.z).abs() <= RENDER_DISTANCE {
-                                            self.meshes_todo.push_back(n_pos);
-                                        }
-                                    }
-                                },
-                                None => {},
-                            }
+                        let n_pos = *chunk + vector![x, y, z];
+                        if !self.meshes_todo.contains(&n_pos)
+                        && terrain_data.needs_mesh(n_pos) && terrain_data.check_neighbors(n_pos)
+                        && n_pos.max_distance(self.player_chunk) <= RENDER_DISTANCE {
+                            self.meshes_todo.push_back(n_pos);
                         }
                     }
                 }
@@ -658,14 +885,20 @@ impl TerrainMesh {
         // also this whole section is just very messy
         'workers: for _ in 0..10 {
             if let Some(chunk) = self.meshes_todo.pop_front() {
-                let tchunk = chunk.clone();
-                let chunk_data = (*terrain_data.chunk_map.get(&chunk).unwrap()).chunk.clone();
+                // an edit can leave a chunk with nothing to draw
+                if !terrain_data.needs_mesh(chunk) {
+                    self.meshed_chunks.remove(&chunk);
+                    self.meshed_chunks_transparent.remove(&chunk);
+                    continue;
+                }
+                let tchunk = chunk;
+                let chunk_data = terrain_data.chunk_map.get(&chunk).unwrap().chunk.clone();
                 let mut neighbor_chunks = Vec::new();
                 for z in -1..=1 {
                     for y in -1..=1 {
                         for x in -1..=1 {
                             match terrain_data.chunk_map.get(&(chunk+vector![x, y, z])) {
-                                Some(nchunk) => neighbor_chunks.push((*nchunk).chunk.clone()),
+                                Some(nchunk) => neighbor_chunks.push(nchunk.chunk.clone()),
                                 None => {
                                     self.meshes_todo.push_back(chunk);
                                     continue 'workers;
@@ -683,7 +916,7 @@ impl TerrainMesh {
         }
 
         // TODO: limit this to a certain number per second based on delta time, similar to veloren
-        let completed_meshes: Vec<(Vector3<i32>, ChunkMeshResponse)> = self.meshing_rx.try_iter().collect();
+        let completed_meshes: Vec<(ChunkPos, ChunkMeshResponse)> = self.meshing_rx.try_iter().collect();
         for (chunk, response) in completed_meshes {
             if terrain_data.chunk_map.contains_key(&chunk) {
                 self.insert_chunk(chunk, Mesh::new(device, &response.opaque_mesh));
@@ -692,3 +925,37 @@ impl TerrainMesh {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_entity::ItemStack;
+
+    #[test]
+    fn chests_keep_their_contents_until_broken() {
+        let mut terrain = Terrain::flat(32);
+        let chest = block::registry().by_name("chest").unwrap();
+        let pos = WorldPos::new(10, 32, 10);
+        terrain.set_block(pos, chest);
+        terrain.take_changes();
+        terrain.edit(|e| match e.block_entity_mut(pos) {
+            Some(BlockEntity::Container(container)) => assert_eq!(container.add(BlockType::STONE, 5), 0),
+            entity => panic!("chest has {:?}", entity),
+        });
+        match terrain.block_entity(pos) {
+            Some(BlockEntity::Container(container)) => assert_eq!(container.slots[0], Some(ItemStack { block: BlockType::STONE, count: 5 })),
+            entity => panic!("chest has {:?}", entity),
+        }
+        // changing the contents saves the chunk
+        assert!(terrain.chunk_map[&pos.chunk()].dirty);
+
+        terrain.set_block(pos, BlockType::AIR);
+        assert_eq!(terrain.block_entity(pos), None);
+        // nor does a new chest get the old contents
+        terrain.set_block(pos, chest);
+        match terrain.block_entity(pos) {
+            Some(BlockEntity::Container(container)) => assert!(container.slots.iter().all(Option::is_none)),
+            entity => panic!("chest has {:?}", entity),
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use crate::gpu_state::GpuState;
-use image::GenericImageView;
-use anyhow::*;
+use crate::block_textures::BlockTextures;
 
 pub struct Texture {
+    #[allow(dead_code)]
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
@@ -49,25 +49,20 @@ impl Texture {
         Self { texture, view, sampler }
     }
 
-    pub fn from_bytes(gpu: &GpuState, bytes: &[u8], label: &str) -> Result<Self> {
-            let img = image::load_from_memory(bytes)?;
-            Self::from_image(gpu, &img, Some(label))
-    }
-
-    pub fn from_image(gpu: &GpuState, img: &image::DynamicImage, label: Option<&str>) -> Result<Self> {
-        let rgba = img.to_rgba8();
-        let dimensions = img.dimensions();
-
+    // one layer per block texture, each with its full mip chain
+    pub fn from_layers(gpu: &GpuState, textures: &BlockTextures, label: &str) -> Self {
+        let layers = textures.layers();
         let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
+            width: textures.size(),
+            height: textures.size(),
+            depth_or_array_layers: layers.len() as u32,
         };
+        let mip_level_count = layers[0].len() as u32;
         let texture = gpu.device.create_texture(
             &wgpu::TextureDescriptor {
-                label,
+                label: Some(label),
                 size,
-                mip_level_count: 1,
+                mip_level_count,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
                 format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -76,35 +71,52 @@ impl Texture {
             }
         );
 
-        gpu.queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
-        );
+        for (layer, mips) in layers.iter().enumerate() {
+            for (mip_level, mip) in mips.iter().enumerate() {
+                gpu.queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        aspect: wgpu::TextureAspect::All,
+                        texture: &texture,
+                        mip_level: mip_level as u32,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: layer as u32,
+                        },
+                    },
+                    mip,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(4 * mip.width()),
+                        rows_per_image: Some(mip.height()),
+                    },
+                    wgpu::Extent3d {
+                        width: mip.width(),
+                        height: mip.height(),
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        // anisotropic filtering only works with every filter linear
         let sampler = gpu.device.create_sampler(
             &wgpu::SamplerDescriptor {
                 address_mode_u: wgpu::AddressMode::ClampToEdge,
                 address_mode_v: wgpu::AddressMode::ClampToEdge,
                 address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: wgpu::FilterMode::Nearest,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: wgpu::FilterMode::Nearest,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
+                anisotropy_clamp: 16,
                 ..Default::default()
             }
         );
 
-        Ok(Self { texture, view, sampler } )
+        Self { texture, view, sampler }
     }
 }
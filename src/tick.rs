@@ -0,0 +1,317 @@
+use crate::terrain::TerrainEdit;
+use crate::block::{self, BlockType, BlockFace};
+use crate::coords::WorldPos;
+use crate::fluid;
+use crate::falling;
+use nalgebra::vector;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+
+pub const TICK_LENGTH: Duration = Duration::from_millis(50);
+const MAX_TICKS_PER_UPDATE: u32 = 10; // after a long frame the rest is skipped, not caught up
+// scheduled updates run per tick, the rest wait for the next one so spreading fluids can't stall
+// the game
+const MAX_SCHEDULED_PER_TICK: usize = 512;
+// blocks picked in each loaded chunk every tick, a given block gets one about every minute
+pub const RANDOM_TICKS_PER_CHUNK: usize = 24;
+
+const LEAF_DECAY_DELAY: u64 = 10; // ticks after a neighbor changes
+const LEAF_SUPPORT_DISTANCE: usize = 4; // steps through leaves to the nearest wood
+
+// What a block does when it gets a tick. Behaviors change the world through the edit, so their
+// changes are reported like any other edit and can schedule updates of their own.
+pub type Behavior = fn(&mut TerrainEdit, WorldPos);
+
+// Tick bookkeeping held by Terrain. Random ticks go to random blocks in every loaded chunk,
+// scheduled ticks to blocks next to a change, some ticks after it.
+pub struct Ticks {
+    tick: u64,
+    accumulator: Duration,
+    random: HashMap<BlockType, Behavior>,
+    scheduled: HashMap<BlockType, (u64, Behavior)>, // delay in ticks and behavior
+    queue: BTreeMap<u64, Vec<WorldPos>>, // by the tick they are due
+    queued: HashSet<WorldPos>,
+}
+
+impl Ticks {
+    pub fn new() -> Self {
+        let mut ticks = Self {
+            tick: 0,
+            accumulator: Duration::ZERO,
+            random: HashMap::new(),
+            scheduled: HashMap::new(),
+            queue: BTreeMap::new(),
+            queued: HashSet::new(),
+        };
+        ticks.register_random(BlockType::DIRT, grass_spread);
+        ticks.register_random(BlockType::GRASS, grass_smother);
+        ticks.register_random(BlockType::LEAVES, leaf_decay);
+        ticks.register_scheduled(BlockType::LEAVES, LEAF_DECAY_DELAY, leaf_decay);
+        for (block, properties) in block::registry().iter() {
+            if properties.fluid {
+                ticks.register_scheduled(block, fluid::FLOW_DELAY, fluid::flow);
+            }
+            if properties.falls {
+                ticks.register_scheduled(block, falling::FALL_DELAY, falling::fall);
+            }
+        }
+        ticks
+    }
+
+    pub fn register_random(&mut self, block: BlockType, behavior: Behavior) {
+        self.random.insert(block, behavior);
+    }
+
+    // behavior runs delay ticks after a block next to one of these changes
+    pub fn register_scheduled(&mut self, block: BlockType, delay: u64, behavior: Behavior) {
+        self.scheduled.insert(block, (delay, behavior));
+    }
+
+    pub fn random_behavior(&self, block: BlockType) -> Option<Behavior> {
+        self.random.get(&block).copied()
+    }
+
+    pub fn scheduled_behavior(&self, block: BlockType) -> Option<Behavior> {
+        self.scheduled.get(&block).map(|(_, behavior)| *behavior)
+    }
+
+    // queues an update of block at block_world_pos if it has a scheduled behavior and none is queued
+    pub fn schedule(&mut self, block_world_pos: WorldPos, block: BlockType) {
+        if let Some((delay, _)) = self.scheduled.get(&block) {
+            if self.queued.insert(block_world_pos) {
+                self.queue.entry(self.tick + delay).or_default().push(block_world_pos);
+            }
+        }
+    }
+
+    // number of ticks to run for dt of time passing
+    pub fn advance(&mut self, dt: Duration) -> u32 {
+        self.accumulator += dt;
+        let mut count = 0;
+        while self.accumulator >= TICK_LENGTH {
+            self.accumulator -= TICK_LENGTH;
+            count += 1;
+        }
+        if count > MAX_TICKS_PER_UPDATE {
+            count = MAX_TICKS_PER_UPDATE;
+        }
+        count
+    }
+
+    // how far into the current tick the time is, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        (self.accumulator.as_secs_f32() / TICK_LENGTH.as_secs_f32()).min(1.0)
+    }
+
+    // starts the next tick, returning the blocks whose scheduled updates are due in it
+    pub fn next_tick(&mut self) -> Vec<WorldPos> {
+        self.tick += 1;
+        let mut due = Vec::new();
+        while let Some(mut entry) = self.queue.first_entry() {
+            if *entry.key() > self.tick {
+                break;
+            }
+            let room = MAX_SCHEDULED_PER_TICK - due.len();
+            if entry.get().len() > room {
+                due.extend(entry.get_mut().drain(..room));
+                break;
+            }
+            due.extend(entry.remove());
+        }
+        for block_world_pos in &due {
+            self.queued.remove(block_world_pos);
+        }
+        due
+    }
+}
+
+// dirt with nothing solid or wet above turns into grass next to grass
+fn grass_spread(edit: &mut TerrainEdit, block_world_pos: WorldPos) {
+    if covered(edit, block_world_pos) {
+        return;
+    }
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                let neighbor = edit.get_block(block_world_pos + vector![x, y, z]);
+                if neighbor.is_some_and(|(_, block, _)| block == BlockType::GRASS) {
+                    edit.set_block(block_world_pos, BlockType::GRASS);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// grass turns back into dirt under opaque blocks and water
+fn grass_smother(edit: &mut TerrainEdit, block_world_pos: WorldPos) {
+    if covered(edit, block_world_pos) {
+        edit.set_block(block_world_pos, BlockType::DIRT);
+    }
+}
+
+fn covered(edit: &TerrainEdit, block_world_pos: WorldPos) -> bool {
+    let above = edit.get_block(block_world_pos + BlockFace::Top.normal());
+    above.is_none_or(|(_, block, _)| block == BlockType::WATER || block::registry().get(block).opaque)
+}
+
+// leaves not connected to wood through a few other leaves disappear, unless the player put them
+// there. Leaves next to unloaded chunks stay, the wood might be there.
+fn leaf_decay(edit: &mut TerrainEdit, block_world_pos: WorldPos) {
+    match edit.get_block(block_world_pos) {
+        Some((_, BlockType::LEAVES, state)) if !state.player_placed() => {},
+        _ => return,
+    }
+
+    let mut visited = HashSet::from([block_world_pos]);
+    let mut todo = VecDeque::from([(block_world_pos, 0)]);
+    while let Some((pos, distance)) = todo.pop_front() {
+        for face in BlockFace::iterator() {
+            let neighbor = pos + face.normal();
+            match edit.get_block(neighbor) {
+                Some((_, BlockType::WOOD, _)) | None => return,
+                Some((_, BlockType::LEAVES, _))
+                    if distance + 1 < LEAF_SUPPORT_DISTANCE && visited.insert(neighbor) => {
+                    todo.push_back((neighbor, distance + 1));
+                },
+                _ => {},
+            }
+        }
+    }
+    edit.set_block(block_world_pos, BlockType::AIR);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockState;
+    use crate::terrain::Terrain;
+
+    fn block_at(terrain: &Terrain, block_world_pos: WorldPos) -> BlockType {
+        terrain.get_block(block_world_pos).unwrap().1
+    }
+
+    #[test]
+    fn advance_counts_whole_ticks() {
+        let mut ticks = Ticks::new();
+        assert_eq!(ticks.advance(TICK_LENGTH / 2), 0);
+        assert!((ticks.progress() - 0.5).abs() < 1e-4);
+        assert_eq!(ticks.advance(TICK_LENGTH * 3), 3);
+        assert!((ticks.progress() - 0.5).abs() < 1e-4);
+        assert_eq!(ticks.advance(TICK_LENGTH / 2), 1);
+        assert_eq!(ticks.progress(), 0.0);
+    }
+
+    #[test]
+    fn advance_skips_ticks_after_a_long_frame() {
+        let mut ticks = Ticks::new();
+        assert_eq!(ticks.advance(TICK_LENGTH * 25 + TICK_LENGTH / 4), MAX_TICKS_PER_UPDATE);
+        // the skipped ticks are not caught up later
+        assert!((ticks.progress() - 0.25).abs() < 1e-4);
+        assert_eq!(ticks.advance(Duration::ZERO), 0);
+    }
+
+    #[test]
+    fn scheduled_updates_are_due_after_their_delay() {
+        let mut ticks = Ticks::new();
+        let pos = WorldPos::new(1, 2, 3);
+        ticks.schedule(pos, BlockType::LEAVES);
+        for _ in 1..LEAF_DECAY_DELAY {
+            assert!(ticks.next_tick().is_empty());
+        }
+        assert_eq!(ticks.next_tick(), vec![pos]);
+        assert!(ticks.next_tick().is_empty());
+    }
+
+    #[test]
+    fn scheduled_updates_are_deduplicated() {
+        let mut ticks = Ticks::new();
+        let pos = WorldPos::new(1, 2, 3);
+        ticks.schedule(pos, BlockType::LEAVES);
+        ticks.next_tick();
+        // already queued, the first update still comes at its own time
+        ticks.schedule(pos, BlockType::LEAVES);
+        let due: Vec<_> = (0..LEAF_DECAY_DELAY * 2).map(|_| ticks.next_tick()).collect();
+        assert_eq!(due.concat(), vec![pos]);
+        assert_eq!(due[LEAF_DECAY_DELAY as usize - 2], vec![pos]);
+
+        // once it ran it can be queued again
+        ticks.schedule(pos, BlockType::LEAVES);
+        let due: Vec<_> = (0..LEAF_DECAY_DELAY).map(|_| ticks.next_tick()).collect();
+        assert_eq!(due.concat(), vec![pos]);
+    }
+
+    #[test]
+    fn updates_over_the_limit_wait_for_the_next_tick() {
+        let mut ticks = Ticks::new();
+        let count = MAX_SCHEDULED_PER_TICK as i32 + 100;
+        let positions: HashSet<_> = (0..count).map(|x| WorldPos::new(x, 0, 0)).collect();
+        for pos in &positions {
+            ticks.schedule(*pos, BlockType::WATER);
+        }
+        for _ in 1..fluid::FLOW_DELAY {
+            assert!(ticks.next_tick().is_empty());
+        }
+        let first = ticks.next_tick();
+        assert_eq!(first.len(), MAX_SCHEDULED_PER_TICK);
+        let second = ticks.next_tick();
+        assert_eq!(second.len(), 100);
+        let due: HashSet<_> = first.into_iter().chain(second).collect();
+        assert_eq!(due, positions);
+        assert!(ticks.next_tick().is_empty());
+    }
+
+    #[test]
+    fn blocks_without_behavior_are_not_scheduled() {
+        let mut ticks = Ticks::new();
+        ticks.schedule(WorldPos::new(1, 2, 3), BlockType::STONE);
+        for _ in 0..LEAF_DECAY_DELAY * 2 {
+            assert!(ticks.next_tick().is_empty());
+        }
+    }
+
+    #[test]
+    fn leaves_near_wood_stay() {
+        let mut terrain = Terrain::flat(32);
+        terrain.set_block(WorldPos::new(10, 32, 10), BlockType::WOOD);
+        let distance = LEAF_SUPPORT_DISTANCE as i32;
+        for x in 1..=distance + 1 {
+            terrain.set_block(WorldPos::new(10 + x, 32, 10), BlockType::LEAVES);
+        }
+
+        for x in 1..=distance {
+            let pos = WorldPos::new(10 + x, 32, 10);
+            terrain.edit(|e| leaf_decay(e, pos));
+            assert_eq!(block_at(&terrain, pos), BlockType::LEAVES);
+        }
+        let farthest = WorldPos::new(10 + distance + 1, 32, 10);
+        terrain.edit(|e| leaf_decay(e, farthest));
+        assert_eq!(block_at(&terrain, farthest), BlockType::AIR);
+    }
+
+    #[test]
+    fn player_placed_leaves_never_decay() {
+        let mut terrain = Terrain::flat(32);
+        let pos = WorldPos::new(10, 32, 10);
+        terrain.set_block_state(pos, BlockType::LEAVES, BlockState::default().with_player_placed(true));
+        terrain.edit(|e| leaf_decay(e, pos));
+        assert_eq!(block_at(&terrain, pos), BlockType::LEAVES);
+    }
+
+    #[test]
+    fn leaves_decay_after_the_wood_is_broken() {
+        let mut terrain = Terrain::flat(32);
+        let wood = WorldPos::new(10, 32, 10);
+        let leaves = WorldPos::new(10, 33, 10);
+        terrain.set_block(wood, BlockType::WOOD);
+        terrain.set_block(leaves, BlockType::LEAVES);
+        terrain.run_ticks(LEAF_DECAY_DELAY * 2);
+        assert_eq!(block_at(&terrain, leaves), BlockType::LEAVES);
+
+        terrain.set_block(wood, BlockType::AIR);
+        terrain.run_ticks(LEAF_DECAY_DELAY);
+        assert_eq!(block_at(&terrain, leaves), BlockType::AIR);
+    }
+}
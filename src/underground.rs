@@ -0,0 +1,252 @@
+use crate::block::{BlockType, BlockFace};
+use crate::chunk::CHUNK_SIZE;
+use crate::coords::{ChunkPos, LocalPos, WorldPos};
+use crate::seed::{self, SeededRng};
+use nalgebra::vector;
+use serde::Deserialize;
+use anyhow::{Context, Result, bail};
+
+const STRATA_SALT: u64 = 16; // plus the stratum's index
+const ORE_SALT: u64 = 32; // plus the ore's index
+const MAX_FREQUENCY: f64 = 64.0; // veins per chunk, every chunk makes the veins of its neighbors too
+
+#[derive(Deserialize)]
+struct UndergroundDef {
+    strata: Vec<StratumDef>,
+    ores: Vec<OreDef>,
+}
+
+#[derive(Deserialize)]
+struct StratumDef {
+    block: String,
+    top: i32,
+    blend: u32,
+}
+
+#[derive(Deserialize)]
+struct OreDef {
+    block: String,
+    min_y: i32,
+    max_y: i32,
+    size: u32,
+    frequency: f64,
+}
+
+#[derive(Clone)]
+pub struct Stratum {
+    pub block: BlockType,
+    pub top: i32,
+    pub blend: i32,
+}
+
+#[derive(Clone)]
+pub struct Ore {
+    pub block: BlockType,
+    pub min_y: i32,
+    pub max_y: i32,
+    pub size: usize,
+    pub frequency: f64,
+}
+
+// The strata and ores that replace stone, see underground.ron. Ore veins are anchored in a chunk
+// and may reach into its neighbors, so every chunk places the veins of the chunks around it too,
+// all made again from the seed. That way a chunk comes out the same whichever is generated first.
+#[derive(Clone, Default)]
+pub struct Underground {
+    pub strata: Vec<Stratum>,
+    pub ores: Vec<Ore>,
+}
+
+impl Underground {
+    // the strata and ores of worlds without an underground.ron of their own. Fails for worlds
+    // whose blocks.ron leaves out their blocks, the default underground is plain stone.
+    pub fn builtin() -> Result<Self> {
+        Self::from_ron(include_str!("underground.ron"))
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self> {
+        let def: UndergroundDef = ron::from_str(source)?;
+        let block = |name: &str| BlockType::from_name(name).with_context(|| format!("unknown block {:?}", name));
+
+        let strata = def.strata.iter()
+            .map(|stratum| {
+                let blend = i32::try_from(stratum.blend).ok()
+                    .filter(|blend| stratum.top.checked_add(*blend).is_some())
+                    .with_context(|| format!("{:?} blends too far above its top", stratum.block))?;
+                Ok(Stratum {
+                    block: block(&stratum.block)?,
+                    top: stratum.top,
+                    blend,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let ores = def.ores.iter()
+            .map(|ore| {
+                if ore.size == 0 || ore.size > CHUNK_SIZE as u32 {
+                    bail!("veins of {:?} must be 1 to {} blocks", ore.block, CHUNK_SIZE);
+                }
+                if ore.min_y > ore.max_y {
+                    bail!("{:?} has no valid range", ore.block);
+                }
+                if !(0.0..=MAX_FREQUENCY).contains(&ore.frequency) {
+                    bail!("{:?} must have 0 to {} veins per chunk", ore.block, MAX_FREQUENCY);
+                }
+                Ok(Ore {
+                    block: block(&ore.block)?,
+                    min_y: ore.min_y,
+                    max_y: ore.max_y,
+                    size: ore.size as usize,
+                    frequency: ore.frequency,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            strata,
+            ores,
+        })
+    }
+
+    // what the block at pos is in place of stone
+    pub fn stone(&self, seed: u64, pos: WorldPos) -> BlockType {
+        for (i, stratum) in self.strata.iter().enumerate().rev() {
+            let inside = pos.y < stratum.top || (pos.y < stratum.top + stratum.blend && {
+                let hash = seed::position_hash(seed::derive(seed, STRATA_SALT + i as u64), pos.x, pos.y, pos.z);
+                hash % (stratum.blend as u64 + 1) < (stratum.top + stratum.blend - pos.y) as u64
+            });
+            if inside {
+                return stratum.block;
+            }
+        }
+        BlockType::STONE
+    }
+
+    // blocks ores can replace
+    fn ground(&self, block: BlockType) -> bool {
+        block == BlockType::STONE || self.strata.iter().any(|stratum| stratum.block == block)
+    }
+
+    // every vein anchored in the chunk, as the ore and the blocks it covers. Veins are random walks
+    // from a random block of the chunk, so they never reach further than the neighboring chunks.
+    pub fn veins(&self, seed: u64, anchor: ChunkPos) -> Vec<(BlockType, Vec<WorldPos>)> {
+        let bottom = anchor.origin().y;
+        let mut veins = Vec::new();
+        for (i, ore) in self.ores.iter().enumerate() {
+            if bottom > ore.max_y || bottom + (CHUNK_SIZE as i32) <= ore.min_y {
+                continue;
+            }
+            let chunk_seed = seed::position_hash(seed, anchor.x, anchor.y, anchor.z);
+            let mut rng = SeededRng::new(seed::derive(chunk_seed, ORE_SALT + i as u64));
+            let count = ore.frequency as u32 + (rng.next_f64() < ore.frequency.fract()) as u32;
+            for _ in 0..count {
+                let mut coordinate = || (rng.next_u64() % CHUNK_SIZE as u64) as usize;
+                let mut pos = anchor.world_pos(LocalPos::new(coordinate(), coordinate(), coordinate()));
+                if !(ore.min_y..=ore.max_y).contains(&pos.y) {
+                    continue;
+                }
+                let mut vein = Vec::with_capacity(ore.size);
+                for _ in 0..ore.size {
+                    vein.push(pos);
+                    let face = BlockFace::iterator().nth((rng.next_u64() % 6) as usize).unwrap();
+                    pos = pos + face.normal();
+                }
+                veins.push((ore.block, vein));
+            }
+        }
+        veins
+    }
+
+    // the parts of the veins around the chunk that are inside it, where there is ground. Where
+    // veins overlap the first one placed stays.
+    pub fn place_ores(&self, seed: u64, chunk_pos: ChunkPos, blocks: &mut [BlockType]) {
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    for (ore, vein) in self.veins(seed, chunk_pos + vector![dx, dy, dz]) {
+                        for pos in vein {
+                            if pos.chunk() != chunk_pos {
+                                continue;
+                            }
+                            let block = &mut blocks[pos.local().index()];
+                            if self.ground(*block) {
+                                *block = ore;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u64 = 7;
+
+    fn underground() -> Underground {
+        Underground::from_ron(r#"(
+            strata: [(block: "dirt", top: 0, blend: 0), (block: "sand", top: -10, blend: 4)],
+            ores: [(block: "gravel", min_y: -64, max_y: 64, size: 20, frequency: 30.0)],
+        )"#).unwrap()
+    }
+
+    #[test]
+    fn strata_blend_into_each_other() {
+        let underground = &underground();
+        let column = |y| (-8..8).map(move |x| underground.stone(SEED, WorldPos::new(x, y, 3))).collect::<Vec<_>>();
+        assert!(column(0).iter().all(|&block| block == BlockType::STONE));
+        assert!(column(-1).iter().all(|&block| block == BlockType::DIRT));
+        let blended = column(-8);
+        assert!(blended.contains(&BlockType::DIRT) && blended.contains(&BlockType::SAND));
+        assert!(column(-11).iter().all(|&block| block == BlockType::SAND));
+    }
+
+    #[test]
+    fn veins_reach_into_neighbors() {
+        let underground = underground();
+        let anchor = ChunkPos::new(0, -1, 0);
+        let mut crossing = 0;
+        for (ore, vein) in underground.veins(SEED, anchor) {
+            for pos in vein.iter().filter(|pos| pos.chunk() != anchor) {
+                // the neighbor places the part of the vein in it, without generating the anchor
+                let mut blocks = vec![BlockType::STONE; crate::chunk::CHUNK_VOLUME];
+                underground.place_ores(SEED, pos.chunk(), &mut blocks);
+                assert_ne!(blocks[pos.local().index()], BlockType::STONE);
+                crossing += (blocks[pos.local().index()] == ore) as usize;
+            }
+        }
+        assert!(crossing > 0);
+    }
+
+    #[test]
+    fn builtin_underground_loads() {
+        let underground = Underground::builtin().unwrap();
+        assert!(!underground.strata.is_empty() && !underground.ores.is_empty());
+    }
+
+    #[test]
+    fn out_of_range_values_fail() {
+        let with = |stratum: &str, ore: &str| Underground::from_ron(&format!(
+            "(strata: [(block: \"dirt\", {}), ], ores: [(block: \"gravel\", min_y: 0, max_y: 10, size: 4, {})])",
+            stratum, ore,
+        ));
+        assert!(with("top: 0, blend: 4", "frequency: 64.0").is_ok());
+        assert!(with("top: 0, blend: 4", "frequency: 65.0").is_err());
+        assert!(with("top: 0, blend: 4", "frequency: -1.0").is_err());
+        assert!(with("top: 0, blend: 4", "frequency: inf").is_err());
+        assert!(with("top: 0, blend: 4", "frequency: NaN").is_err());
+        assert!(with("top: 0, blend: 2147483648", "frequency: 1.0").is_err());
+        assert!(with("top: 10, blend: 2147483640", "frequency: 1.0").is_err());
+    }
+
+    #[test]
+    fn default_underground_is_plain_stone() {
+        let underground = Underground::default();
+        assert_eq!(underground.stone(SEED, WorldPos::new(0, -100, 0)), BlockType::STONE);
+        let mut blocks = vec![BlockType::STONE; crate::chunk::CHUNK_VOLUME];
+        underground.place_ores(SEED, ChunkPos::new(0, -2, 0), &mut blocks);
+        assert!(blocks.iter().all(|&block| block == BlockType::STONE));
+    }
+}
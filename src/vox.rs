@@ -0,0 +1,234 @@
+use crate::block::{BlockType, BlockState, registry};
+use crate::edit::{Clipboard, Cuboid};
+use crate::terrain::Terrain;
+use nalgebra::vector;
+use anyhow::{Context, Result, bail};
+use std::{fs, path::Path};
+
+// MagicaVoxel .vox files, https://github.com/ephtracy/voxel-model/blob/master/MagicaVoxel-file-format-vox.txt
+// Only the first model in a file is used, and states (e.g. log orientation) are not kept. Blocks with
+// ids over 255 don't fit in the palette and are left out.
+
+const VOX_VERSION: i32 = 150;
+const MAX_SIZE: usize = 256; // models can't be bigger than this along any axis
+
+pub fn load(path: impl AsRef<Path>) -> Result<Clipboard> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    from_bytes(&bytes).with_context(|| format!("loading {}", path.display()))
+}
+
+pub fn save(clipboard: &Clipboard, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    fs::write(path, to_bytes(clipboard)?).with_context(|| format!("writing {}", path.display()))
+}
+
+// blocks in unloaded chunks are exported as empty
+pub fn export(terrain: &Terrain, region: Cuboid, path: impl AsRef<Path>) -> Result<()> {
+    save(&Clipboard::copy(terrain, region), path)
+}
+
+// blocks are exported as their registry color, at the palette index of their id
+fn palette_index(block: BlockType) -> Option<u8> {
+    match block.0 {
+        1..=255 => Some(block.0 as u8),
+        _ => None,
+    }
+}
+
+// imported colors become the block with the closest registry color
+fn closest_block(color: [u8; 4]) -> BlockType {
+    let distance = |c: &[u8; 3]| -> i32 {
+        (0..3).map(|i| (c[i] as i32 - color[i] as i32).pow(2)).sum()
+    };
+    registry().iter()
+        .filter(|(block, _)| *block != BlockType::AIR)
+        .min_by_key(|(_, properties)| distance(&properties.color))
+        .map(|(block, _)| block)
+        .unwrap()
+}
+
+// .vox is z up, so its (x, y, z) is our (x, -z, y), keeping models the right way around
+pub fn to_bytes(clipboard: &Clipboard) -> Result<Vec<u8>> {
+    let size = clipboard.size();
+    if size.x > MAX_SIZE || size.y > MAX_SIZE || size.z > MAX_SIZE {
+        bail!("{}x{}x{} is too big for a .vox model, the limit is {}", size.x, size.y, size.z, MAX_SIZE);
+    }
+
+    let mut voxels = Vec::new();
+    for z in 0..size.z {
+        for y in 0..size.y {
+            for x in 0..size.x {
+                if let Some(index) = palette_index(clipboard.get(vector![x, y, z]).0) {
+                    voxels.extend_from_slice(&[x as u8, (size.z - 1 - z) as u8, y as u8, index]);
+                }
+            }
+        }
+    }
+
+    let mut size_content = Vec::new();
+    for s in [size.x, size.z, size.y] {
+        size_content.extend_from_slice(&(s as i32).to_le_bytes());
+    }
+    let mut xyzi_content = ((voxels.len() / 4) as i32).to_le_bytes().to_vec();
+    xyzi_content.extend_from_slice(&voxels);
+    // palette entry i is color index i+1
+    let mut rgba_content = vec![0; 256*4];
+    for (block, properties) in registry().iter() {
+        if let Some(index) = palette_index(block) {
+            let i = index as usize - 1;
+            rgba_content[i*4..i*4 + 3].copy_from_slice(&properties.color);
+            rgba_content[i*4 + 3] = 255;
+        }
+    }
+
+    let mut children = Vec::new();
+    write_chunk(&mut children, b"SIZE", &size_content, &[]);
+    write_chunk(&mut children, b"XYZI", &xyzi_content, &[]);
+    write_chunk(&mut children, b"RGBA", &rgba_content, &[]);
+
+    let mut bytes = b"VOX ".to_vec();
+    bytes.extend_from_slice(&VOX_VERSION.to_le_bytes());
+    write_chunk(&mut bytes, b"MAIN", &[], &children);
+    Ok(bytes)
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&(content.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(&(children.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(content);
+    bytes.extend_from_slice(children);
+}
+
+fn read_i32(bytes: &[u8], offset: usize) -> Result<i32> {
+    match bytes.get(offset..offset + 4) {
+        Some(b) => Ok(i32::from_le_bytes(b.try_into().unwrap())),
+        None => bail!("unexpected end of file"),
+    }
+}
+
+// a length or count, which a malformed file can make negative
+fn read_len(bytes: &[u8], offset: usize) -> Result<usize> {
+    let value = read_i32(bytes, offset)?;
+    usize::try_from(value).with_context(|| format!("negative length {}", value))
+}
+
+// offsets and lengths added up, which a malformed file can make overflow
+fn sum(values: &[usize]) -> Result<usize> {
+    values.iter().try_fold(0usize, |sum, value| sum.checked_add(*value)).context("lengths overflow")
+}
+
+// empty voxels become air
+pub fn from_bytes(bytes: &[u8]) -> Result<Clipboard> {
+    if bytes.get(0..4) != Some(b"VOX ") {
+        bail!("not a .vox file");
+    }
+    if bytes.get(8..12) != Some(b"MAIN") {
+        bail!("missing MAIN chunk");
+    }
+
+    // the rest of the file is MAIN's children, a flat list of chunks
+    let mut size = None;
+    let mut voxels = None;
+    let mut palette = None;
+    let mut offset = sum(&[20, read_len(bytes, 12)?])?;
+    while offset < bytes.len() {
+        let id = bytes.get(offset..offset + 4).context("unexpected end of file")?;
+        let content_len = read_len(bytes, offset + 4)?;
+        let children_len = read_len(bytes, offset + 8)?;
+        let content = bytes.get(offset + 12..sum(&[offset, 12, content_len])?).context("unexpected end of file")?;
+        match id {
+            b"SIZE" if size.is_none() => size = Some([
+                read_i32(content, 0)?,
+                read_i32(content, 4)?,
+                read_i32(content, 8)?,
+            ]),
+            b"XYZI" if voxels.is_none() => {
+                let count = read_len(content, 0)?;
+                let end = count.checked_mul(4).and_then(|len| len.checked_add(4)).context("too many voxels")?;
+                voxels = Some(content.get(4..end).context("voxel data is truncated")?);
+            },
+            b"RGBA" => palette = Some(content),
+            _ => {},
+        }
+        offset = sum(&[offset, 12, content_len, children_len])?;
+    }
+
+    let (size, voxels) = match (size, voxels) {
+        (Some(size), Some(voxels)) => (size, voxels),
+        _ => bail!("file has no model"),
+    };
+    // files without a palette use MagicaVoxel's default one, which we don't have
+    let palette = palette.context("file has no palette")?;
+    if palette.len() < 256*4 {
+        bail!("palette is truncated");
+    }
+    if size.iter().any(|s| *s <= 0 || *s as usize > MAX_SIZE) {
+        bail!("bad model size {:?}", size);
+    }
+
+    let mut clipboard = Clipboard::new(vector![size[0] as usize, size[2] as usize, size[1] as usize]);
+    for voxel in voxels.chunks_exact(4) {
+        let (x, y, z, index) = (voxel[0] as usize, voxel[1] as usize, voxel[2] as usize, voxel[3] as usize);
+        if x >= size[0] as usize || y >= size[1] as usize || z >= size[2] as usize || index == 0 {
+            bail!("voxel {:?} is outside the model or has no color", voxel);
+        }
+        let color = palette[(index - 1)*4..index*4].try_into().unwrap();
+        let pos = vector![x, z, size[1] as usize - 1 - y];
+        clipboard.set(pos, closest_block(color), BlockState::default());
+    }
+    Ok(clipboard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> Clipboard {
+        let mut clipboard = Clipboard::new(vector![3, 2, 4]);
+        let blocks = [BlockType::GRASS, BlockType::DIRT, BlockType::STONE, BlockType::SAND, BlockType::WOOD];
+        for (i, (x, y, z)) in [(0, 0, 0), (2, 0, 1), (1, 1, 3), (0, 1, 2), (2, 1, 0)].into_iter().enumerate() {
+            clipboard.set(vector![x, y, z], blocks[i], BlockState::default());
+        }
+        clipboard
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let clipboard = model();
+        let imported = from_bytes(&to_bytes(&clipboard).unwrap()).unwrap();
+        assert_eq!(imported.size(), clipboard.size());
+        for z in 0..4 {
+            for y in 0..2 {
+                for x in 0..3 {
+                    let pos = vector![x, y, z];
+                    assert_eq!(imported.get(pos).0, clipboard.get(pos).0, "at {:?}", pos);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_files_fail() {
+        let bytes = to_bytes(&model()).unwrap();
+        for len in 0..bytes.len() {
+            assert!(from_bytes(&bytes[..len]).is_err(), "{} of {} bytes loaded", len, bytes.len());
+        }
+    }
+
+    #[test]
+    fn bad_lengths_fail() {
+        let bytes = to_bytes(&model()).unwrap();
+        // MAIN's content length, SIZE's content and children lengths and XYZI's voxel count
+        let size_chunk = 20;
+        let xyzi_chunk = size_chunk + 12 + 12;
+        for offset in [12, size_chunk + 4, size_chunk + 8, xyzi_chunk + 12] {
+            for value in [-1, i32::MIN, i32::MAX] {
+                let mut bad = bytes.clone();
+                bad[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+                assert!(from_bytes(&bad).is_err(), "{} at {} loaded", value, offset);
+            }
+        }
+    }
+}
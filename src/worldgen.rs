@@ -0,0 +1,823 @@
+use crate::block::BlockType;
+use crate::biome::{Biome, BiomeProperties, BIOMES};
+use crate::chunk::{Chunk, CHUNK_SIZE, CHUNK_VOLUME};
+use crate::coords::{ChunkPos, LocalPos, WorldPos};
+use crate::seed::{self, SeededRng};
+use crate::underground::Underground;
+use crate::structure::Structure;
+use noise::{NoiseFn, Perlin, Curve};
+use serde::Deserialize;
+use anyhow::{Context, Result, bail};
+use std::sync::Arc;
+
+// Makes the chunks of a world that were never edited. Called from worker threads, so the same
+// position must always give the same chunk.
+pub trait WorldGenerator: Send + Sync {
+    fn generate(&self, chunk_pos: ChunkPos) -> Chunk;
+}
+
+// Generators a world can be made with, read from generator.ron in the world directory
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+pub enum Preset {
+    #[default]
+    Noise,
+    // (block name, thickness) from the bottom up, starting at y = 0
+    Flat(Vec<(String, u32)>),
+    Void,
+    // a one block thick floor of alternating squares
+    Checkerboard {
+        blocks: (String, String),
+        size: u32,
+        height: i32,
+    },
+}
+
+impl Preset {
+    // underground is what the noise terrain has below the surface, the other presets ignore it
+    pub fn build(&self, seed: u64, underground: Underground) -> Result<Arc<dyn WorldGenerator>> {
+        Ok(match self {
+            Preset::Noise => Arc::new(NoiseGenerator::new(seed, underground)),
+            Preset::Flat(layers) => {
+                let layers = layers.iter()
+                    .map(|(name, thickness)| Ok((block_named(name)?, *thickness)))
+                    .collect::<Result<_>>()?;
+                Arc::new(FlatGenerator::new(layers))
+            },
+            Preset::Void => Arc::new(VoidGenerator),
+            Preset::Checkerboard { blocks, size, height } => {
+                if *size == 0 {
+                    bail!("checkerboard squares need a size");
+                }
+                let blocks = (block_named(&blocks.0)?, block_named(&blocks.1)?);
+                Arc::new(CheckerboardGenerator::new(blocks, *size, *height))
+            },
+        })
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self> {
+        ron::from_str(source).context("invalid generator preset")
+    }
+}
+
+fn block_named(name: &str) -> Result<BlockType> {
+    BlockType::from_name(name).with_context(|| format!("unknown block {:?}", name))
+}
+
+const SEA_LEVEL: i32 = 40;
+const BEACH_LEVEL: i32 = 42;
+const SNOW_LINE: i32 = 100; // surfaces this high are snow in every biome
+const SURFACE_DEPTH: i32 = 2; // blocks of sand/dirt below the top block
+
+// Column heights use the biome parameters averaged over the biomes around them, sampled every
+// BIOME_CELL blocks and up to BLEND_RADIUS cells away, so borders slope instead of stepping.
+const BIOME_CELL: i32 = 4;
+const BLEND_RADIUS: i32 = 2;
+
+// Blocks are solid where the density, the height of the column above them plus 3D noise, is
+// positive. The noise reaches OVERHANG blocks, scaled like the biome's hills, and makes overhangs
+// and arches.
+const OVERHANG: f64 = 6.0;
+
+// Caves are air where the cheese noise is high, making caverns, or where both spaghetti noises
+// are near zero, making tunnels along where their zero surfaces cross. Below sea level they keep
+// CAVE_ROOF blocks below anything the sea could reach, so they never open up into water.
+const CHEESE_THRESHOLD: f64 = 0.45;
+const SPAGHETTI_WIDTH: f64 = 0.05;
+const CAVE_ROOF: f64 = 8.0;
+
+const TERRAIN_NOISE_SALT: u64 = 1;
+const TEMPERATURE_SALT: u64 = 2;
+const HUMIDITY_SALT: u64 = 3;
+const RUGGEDNESS_SALT: u64 = 4;
+const OVERHANG_SALT: u64 = 5;
+const CHEESE_SALT: u64 = 6;
+const SPAGHETTI_SALTS: [u64; 2] = [7, 8];
+const TREE_SALT: u64 = 9;
+const PLANT_SALT: u64 = 10;
+
+// Plants are rolled for from the position of the surface block they grow on, so the chunks a
+// plant grows across agree on it. Chunks look that far below them for ground.
+const MAX_PLANT_HEIGHT: i32 = 3;
+
+// Trees are tried at TREE_ATTEMPTS random columns of every column of chunks, and grow where the
+// biome's chance of a tree per block of ground, scaled up to the attempts, says so. Enough for
+// biomes with up to one tree per 21 blocks.
+const TREE_ATTEMPTS: u32 = 48;
+
+// The 2D part of the terrain at one column
+#[derive(Debug, Clone, Copy)]
+struct Column {
+    height: i32, // world y of the highest solid block, before the 3D noise
+    biome: Biome,
+    overhang: f64, // how far the 3D noise moves the surface, at most
+}
+
+// Hills and oceans from layered perlin noise, shaped and covered by biomes, with overhangs and
+// caves from 3D noise and strata and ores underground
+pub struct NoiseGenerator {
+    seed: u64,
+    perlin: Perlin,
+    continental_noise: Curve<f64, Perlin, 2>,
+    temperature: Perlin,
+    humidity: Perlin,
+    ruggedness: Perlin,
+    overhang: Perlin,
+    cheese: Perlin,
+    spaghetti: [Perlin; 2],
+    biomes: Vec<BiomeProperties>, // indexed by Biome
+    underground: Underground,
+    tree: Structure,
+    // None when the world's blocks.ron doesn't have them, then mountains keep their biome's top and
+    // water doesn't freeze
+    snow: Option<BlockType>,
+    ice: Option<BlockType>,
+}
+
+impl NoiseGenerator {
+    pub fn new(seed: u64, underground: Underground) -> Self {
+        let noise = |salt| Perlin::new(seed::derive(seed, salt) as u32);
+        let perlin = noise(TERRAIN_NOISE_SALT);
+        let mut continental_noise: Curve<f64, Perlin, 2> = Curve::new(perlin);
+        continental_noise = continental_noise.add_control_point(-1.01, 50.0);
+        continental_noise = continental_noise.add_control_point(-1.0, 0.0);
+        continental_noise = continental_noise.add_control_point(-0.2, 50.0);
+        continental_noise = continental_noise.add_control_point(0.2, 60.0);
+        continental_noise = continental_noise.add_control_point(0.6, 60.0);
+        continental_noise = continental_noise.add_control_point(1.0, 150.0);
+        continental_noise = continental_noise.add_control_point(1.01, 100.0);
+
+        let biomes: Vec<BiomeProperties> = BIOMES.iter().map(|biome| biome.properties()).collect();
+        assert!(
+            biomes.iter().flat_map(|biome| &biome.plants).all(|&(_, _, max_height)| max_height as i32 <= MAX_PLANT_HEIGHT),
+            "plants grow at most MAX_PLANT_HEIGHT blocks tall",
+        );
+
+        Self {
+            seed,
+            perlin,
+            continental_noise,
+            temperature: noise(TEMPERATURE_SALT),
+            humidity: noise(HUMIDITY_SALT),
+            ruggedness: noise(RUGGEDNESS_SALT),
+            overhang: noise(OVERHANG_SALT),
+            cheese: noise(CHEESE_SALT),
+            spaghetti: SPAGHETTI_SALTS.map(noise),
+            biomes,
+            underground,
+            tree: Structure::tree(),
+            snow: BlockType::from_name("snow"),
+            ice: BlockType::from_name("ice"),
+        }
+    }
+
+    pub fn biome(&self, x: i32, z: i32) -> Biome {
+        let climate = [x as f64 / 512.0, z as f64 / 512.0];
+        Biome::from_climate(
+            self.temperature.get(climate),
+            self.humidity.get(climate),
+            self.ruggedness.get([x as f64 / 384.0, z as f64 / 384.0]),
+        )
+    }
+
+    // (height offset, height scale) blended at corners by corners columns BIOME_CELL blocks apart,
+    // starting at the column at cell * BIOME_CELL
+    fn blended_heights(&self, cell: (i32, i32), corners: i32) -> Vec<(f64, f64)> {
+        let span = corners + 2*BLEND_RADIUS;
+        let sampled: Vec<&BiomeProperties> = (0..span*span)
+            .map(|i| {
+                let x = (cell.0 + i % span - BLEND_RADIUS) * BIOME_CELL;
+                let z = (cell.1 + i / span - BLEND_RADIUS) * BIOME_CELL;
+                &self.biomes[self.biome(x, z) as usize]
+            })
+            .collect();
+
+        let samples = ((2*BLEND_RADIUS + 1) * (2*BLEND_RADIUS + 1)) as f64;
+        (0..corners*corners)
+            .map(|i| {
+                let (cx, cz) = (i % corners, i / corners);
+                let mut sum = (0.0, 0.0);
+                for dz in 0..=2*BLEND_RADIUS {
+                    for dx in 0..=2*BLEND_RADIUS {
+                        let biome = sampled[((cx + dx) + (cz + dz) * span) as usize];
+                        sum.0 += biome.height_offset;
+                        sum.1 += biome.height_scale;
+                    }
+                }
+                (sum.0 / samples, sum.1 / samples)
+            })
+            .collect()
+    }
+
+    // the size by size columns from world x, z on, x fastest. A column comes out the same in
+    // every area that has it.
+    fn columns(&self, x: i32, z: i32, size: usize) -> Vec<Column> {
+        let cell = (x.div_euclid(BIOME_CELL), z.div_euclid(BIOME_CELL));
+        let last = (x + size as i32 - 1, z + size as i32 - 1);
+        // so every column lies between four corners
+        let corners = (last.0.div_euclid(BIOME_CELL) - cell.0).max(last.1.div_euclid(BIOME_CELL) - cell.1) + 2;
+        let blended = self.blended_heights(cell, corners);
+
+        (0..size*size)
+            .map(|i| {
+                let (x, z) = (x + (i % size) as i32, z + (i / size) as i32);
+                let px = x as f64;
+                let pz = z as f64;
+
+                // bilinear between the blended heights around the column
+                let (cx, cz) = (x.div_euclid(BIOME_CELL) - cell.0, z.div_euclid(BIOME_CELL) - cell.1);
+                let tx = x.rem_euclid(BIOME_CELL) as f64 / BIOME_CELL as f64;
+                let tz = z.rem_euclid(BIOME_CELL) as f64 / BIOME_CELL as f64;
+                let corner = |dx, dz| blended[((cx + dx) + (cz + dz) * corners) as usize];
+                let lerp = |a: (f64, f64), b: (f64, f64), t: f64| (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+                let (offset, scale) = lerp(lerp(corner(0, 0), corner(1, 0), tx), lerp(corner(0, 1), corner(1, 1), tx), tz);
+
+                let continental = &self.continental_noise.get([
+                    px / 320.0,
+                    pz / 320.0,
+                ]);
+                let nv1 = self.perlin.get([
+                    px / 160.0,
+                    pz / 160.0,
+                ]) * 16.0;
+                let nv2 = self.perlin.get([
+                    px / 80.0,
+                    pz / 80.0,
+                ]) * 16.0;
+                let nv3 = self.perlin.get([
+                    px / 40.0,
+                    pz / 40.0,
+                ]) * 16.0;
+                let terrain_height = continental + offset + scale * (nv1 + 0.5*nv2 + 0.25*nv3);
+                Column {
+                    height: terrain_height.ceil() as i32 - 1,
+                    biome: self.biome(x, z),
+                    overhang: OVERHANG * scale,
+                }
+            })
+            .collect()
+    }
+
+    fn overhang_noise(&self, [x, y, z]: [f64; 3]) -> f64 {
+        self.overhang.get([x / 32.0, y / 16.0, z / 32.0])
+    }
+
+    fn cheese_noise(&self, [x, y, z]: [f64; 3]) -> f64 {
+        self.cheese.get([x / 80.0, y / 40.0, z / 80.0])
+    }
+
+    fn spaghetti_noise(&self, i: usize, [x, y, z]: [f64; 3]) -> f64 {
+        self.spaghetti[i].get([x / 64.0, y / 48.0, z / 64.0])
+    }
+
+    // the plant growing on the surface block at pos, if any, and how tall it is
+    fn plant(&self, biome: &BiomeProperties, pos: WorldPos) -> Option<(BlockType, i32)> {
+        let mut rng = SeededRng::new(seed::derive(seed::position_hash(self.seed, pos.x, pos.y, pos.z), PLANT_SALT));
+        let mut roll = rng.next_f64();
+        for &(plant, chance, max_height) in &biome.plants {
+            if roll < chance {
+                return Some((plant, 1 + (rng.next_u64() % max_height as u64) as i32));
+            }
+            roll -= chance;
+        }
+        None
+    }
+
+    // world y of the column's highest solid block if a tree can grow on it, worked out for the
+    // one column exactly like generate does for the whole chunk
+    fn tree_ground(&self, x: i32, z: i32, column: &Column) -> Option<i32> {
+        let solid = |y: i32| {
+            let overhang = Lattice::at(WorldPos::new(x, y, z), |p| self.overhang_noise(p));
+            (column.height - y) as f64 + column.overhang * overhang >= 0.0
+        };
+        let y = (column.height - surface_reach(column)..=column.height + surface_reach(column)).rev().find(|&y| solid(y))?;
+        let pos = WorldPos::new(x, y, z);
+        let carved = Lattice::at(pos, |p| self.cheese_noise(p)) > CHEESE_THRESHOLD
+            || (0..2).all(|i| Lattice::at(pos, |p| self.spaghetti_noise(i, p)).abs() < SPAGHETTI_WIDTH);
+        ((BEACH_LEVEL..SNOW_LINE).contains(&y) && !carved).then_some(y)
+    }
+}
+
+// the 3D noise moves a column's surface by at most this much
+fn surface_reach(column: &Column) -> i32 {
+    column.overhang.ceil() as i32 + 1
+}
+
+impl WorldGenerator for NoiseGenerator {
+    fn generate(&self, chunk_pos: ChunkPos) -> Chunk {
+        let origin = chunk_pos.origin();
+        let bottom = origin.y;
+        let top = bottom + CHUNK_SIZE as i32 - 1;
+
+        // the columns around the chunk too, as far as trees growing there reach into it
+        let reach = self.tree.reach();
+        let size = CHUNK_SIZE + 2*reach as usize;
+        let columns = self.columns(origin.x - reach, origin.z - reach, size);
+        let column = |x: i32, z: i32| columns[(x - origin.x + reach) as usize + (z - origin.z + reach) as usize * size];
+        let (tree_bottom, tree_top) = self.tree.height();
+
+        // chunks entirely above the surface and its trees are a single block, skip the block array
+        let max_surface = columns.iter()
+            .map(|column| column.height as f64 + column.overhang)
+            .fold(f64::MIN, f64::max);
+        if bottom as f64 > max_surface + tree_top as f64 && bottom > SEA_LEVEL {
+            return Chunk::filled(BlockType::AIR);
+        } else if bottom as f64 > max_surface && top < SEA_LEVEL {
+            return Chunk::filled(BlockType::WATER);
+        }
+
+        // The blocks above the chunk are needed too, to know how deep below the surface blocks are
+        // and how much room plants have, and the ones below it for the plants growing up into it.
+        // The lattices start a whole number of cells lower, so they sample the same points.
+        let height = CHUNK_SIZE + (SURFACE_DEPTH + 1).max(MAX_PLANT_HEIGHT) as usize;
+        let below = (MAX_PLANT_HEIGHT as usize).next_multiple_of(LATTICE_CELL);
+        let lattice_origin = WorldPos::new(origin.x, bottom - below as i32, origin.z);
+        let overhang = Lattice::new(lattice_origin, below + height, |p| self.overhang_noise(p));
+        let cheese = Lattice::new(lattice_origin, below + CHUNK_SIZE, |p| self.cheese_noise(p));
+        let spaghetti = [0, 1].map(|i| Lattice::new(lattice_origin, below + CHUNK_SIZE, |p| self.spaghetti_noise(i, p)));
+
+        let mut blocks = [BlockType::AIR; CHUNK_VOLUME];
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let column = column(origin.x + x as i32, origin.z + z as i32);
+                let biome = &self.biomes[column.biome as usize];
+                // world y of the top of the solid blocks above, how many there are, and how much
+                // air is above them
+                let mut run_top = 0;
+                let mut run = 0;
+                let mut air = 0;
+                let mut room = 0;
+                for ly in (0..below + height).rev() {
+                    let y = lattice_origin.y + ly as i32;
+                    let density = (column.height - y) as f64 + column.overhang * overhang.get(x, ly, z);
+                    if density < 0.0 {
+                        run = 0;
+                        air += 1;
+                    } else {
+                        if run == 0 {
+                            run_top = y;
+                            room = air;
+                        }
+                        run += 1;
+                        air = 0;
+                    }
+                    if ly >= below + CHUNK_SIZE {
+                        continue;
+                    }
+
+                    let depth = run_top - y;
+                    let block = if run == 0 {
+                        match self.ice {
+                            Some(ice) if y == SEA_LEVEL && biome.freezes => ice,
+                            _ if y <= SEA_LEVEL => BlockType::WATER,
+                            _ => BlockType::AIR,
+                        }
+                    } else if (y > SEA_LEVEL || (y as f64) < column.height as f64 - column.overhang - CAVE_ROOF)
+                    && cave(&cheese, &spaghetti, x, ly, z) {
+                        BlockType::AIR
+                    } else if depth > SURFACE_DEPTH {
+                        // below the chunk only the surface matters
+                        if ly < below {
+                            continue;
+                        }
+                        self.underground.stone(self.seed, chunk_pos.world_pos(LocalPos::new(x, ly - below, z)))
+                    } else if run_top < BEACH_LEVEL {
+                        BlockType::SAND
+                    } else if depth == 0 {
+                        match self.snow {
+                            Some(snow) if run_top >= SNOW_LINE => snow,
+                            _ => biome.top,
+                        }
+                    } else {
+                        biome.filler
+                    };
+                    if ly >= below {
+                        blocks[LocalPos::new(x, ly - below, z).index()] = block;
+                    }
+
+                    // on surface blocks above the water, which always have air above them
+                    if run == 1 && y >= SEA_LEVEL && block == biome.top {
+                        let pos = WorldPos::new(origin.x + x as i32, y, origin.z + z as i32);
+                        if let Some((plant, plant_height)) = self.plant(biome, pos) {
+                            for plant_y in y + 1..=y + plant_height.min(room) {
+                                if (bottom..=top).contains(&plant_y) {
+                                    blocks[LocalPos::new(x, (plant_y - bottom) as usize, z).index()] = plant;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        self.underground.place_ores(self.seed, chunk_pos, &mut blocks);
+
+        // Trees of this column of chunks and the ones around it that reach into the chunk. Every
+        // chunk makes the same attempts for a column, so a tree comes out whole and the same
+        // whichever of the chunks it's in is generated first.
+        let chunks = |v: i32| (v - reach).div_euclid(CHUNK_SIZE as i32)..=(v + CHUNK_SIZE as i32 - 1 + reach).div_euclid(CHUNK_SIZE as i32);
+        for chunk_z in chunks(origin.z) {
+            for chunk_x in chunks(origin.x) {
+                let column_seed = seed::position_hash(self.seed, chunk_x, 0, chunk_z);
+                let mut rng = SeededRng::new(seed::derive(column_seed, TREE_SALT));
+                for _ in 0..TREE_ATTEMPTS {
+                    let x = chunk_x * CHUNK_SIZE as i32 + (rng.next_u64() % CHUNK_SIZE as u64) as i32;
+                    let z = chunk_z * CHUNK_SIZE as i32 + (rng.next_u64() % CHUNK_SIZE as u64) as i32;
+                    let roll = rng.next_f64();
+
+                    let near = |v: i32, origin: i32| (origin - reach..origin + CHUNK_SIZE as i32 + reach).contains(&v);
+                    if !near(x, origin.x) || !near(z, origin.z) {
+                        continue;
+                    }
+                    let column = column(x, z);
+                    let chance = self.biomes[column.biome as usize].trees * (CHUNK_SIZE*CHUNK_SIZE) as f64 / TREE_ATTEMPTS as f64;
+                    if roll >= chance
+                    || column.height + surface_reach(&column) + tree_top < bottom
+                    || column.height - surface_reach(&column) + tree_bottom > top {
+                        continue;
+                    }
+                    if let Some(ground) = self.tree_ground(x, z, &column) {
+                        self.tree.place(WorldPos::new(x, ground, z), chunk_pos, &mut blocks);
+                    }
+                }
+            }
+        }
+
+        Chunk::from_blocks(&blocks)
+    }
+}
+
+// whether a solid block at the position is carved out by a cave
+fn cave(cheese: &Lattice, spaghetti: &[Lattice; 2], x: usize, y: usize, z: usize) -> bool {
+    cheese.get(x, y, z) > CHEESE_THRESHOLD
+        || spaghetti.iter().all(|noise| noise.get(x, y, z).abs() < SPAGHETTI_WIDTH)
+}
+
+const LATTICE_CELL: usize = 4;
+
+// Noise sampled every LATTICE_CELL blocks and interpolated in between, for a fraction of the
+// samples. The noise is smooth enough at the scales used that it hardly changes.
+struct Lattice {
+    values: Vec<f64>,
+    points: [usize; 3],
+}
+
+impl Lattice {
+    // covers the chunk's columns from origin up height blocks, noise gets world positions
+    fn new(origin: WorldPos, height: usize, noise: impl Fn([f64; 3]) -> f64) -> Self {
+        let points = [
+            CHUNK_SIZE / LATTICE_CELL + 1,
+            height.div_ceil(LATTICE_CELL) + 1,
+            CHUNK_SIZE / LATTICE_CELL + 1,
+        ];
+        let mut values = Vec::with_capacity(points.iter().product());
+        for z in 0..points[2] {
+            for y in 0..points[1] {
+                for x in 0..points[0] {
+                    values.push(noise([
+                        (origin.x + (x * LATTICE_CELL) as i32) as f64,
+                        (origin.y + (y * LATTICE_CELL) as i32) as f64,
+                        (origin.z + (z * LATTICE_CELL) as i32) as f64,
+                    ]));
+                }
+            }
+        }
+
+        Self {
+            values,
+            points,
+        }
+    }
+
+    // at the block x, y, z from the origin
+    fn get(&self, x: usize, y: usize, z: usize) -> f64 {
+        let cell = |v: usize| (v / LATTICE_CELL, (v % LATTICE_CELL) as f64 / LATTICE_CELL as f64);
+        let ((cx, tx), (cy, ty), (cz, tz)) = (cell(x), cell(y), cell(z));
+        trilinear([tx, ty, tz], |dx, dy, dz| {
+            self.values[(cx + dx) + (cy + dy) * self.points[0] + (cz + dz) * self.points[0] * self.points[1]]
+        })
+    }
+
+    // the value at a single block without a lattice, the same as get gives in any lattice with it
+    fn at(pos: WorldPos, noise: impl Fn([f64; 3]) -> f64) -> f64 {
+        let cell = LATTICE_CELL as i32;
+        let corner = |v: i32| (v.div_euclid(cell) * cell, v.rem_euclid(cell) as f64 / cell as f64);
+        let ((x, tx), (y, ty), (z, tz)) = (corner(pos.x), corner(pos.y), corner(pos.z));
+        trilinear([tx, ty, tz], |dx, dy, dz| noise([
+            (x + dx as i32 * cell) as f64,
+            (y + dy as i32 * cell) as f64,
+            (z + dz as i32 * cell) as f64,
+        ]))
+    }
+}
+
+// between the values at the corners of a cell, t is the position inside it
+fn trilinear(t: [f64; 3], value: impl Fn(usize, usize, usize) -> f64) -> f64 {
+    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+    lerp(
+        lerp(lerp(value(0, 0, 0), value(1, 0, 0), t[0]), lerp(value(0, 1, 0), value(1, 1, 0), t[0]), t[1]),
+        lerp(lerp(value(0, 0, 1), value(1, 0, 1), t[0]), lerp(value(0, 1, 1), value(1, 1, 1), t[0]), t[1]),
+        t[2],
+    )
+}
+
+// Horizontal layers of blocks over nothing, the same at every x and z
+pub struct FlatGenerator {
+    layers: Vec<BlockType>, // block at each y from 0 up
+}
+
+impl FlatGenerator {
+    // (block, thickness) from the bottom up
+    pub fn new(layers: Vec<(BlockType, u32)>) -> Self {
+        Self {
+            layers: layers.into_iter()
+                .flat_map(|(block, thickness)| std::iter::repeat_n(block, thickness as usize))
+                .collect(),
+        }
+    }
+
+    fn block_at(&self, y: i32) -> BlockType {
+        usize::try_from(y).ok()
+            .and_then(|y| self.layers.get(y).copied())
+            .unwrap_or(BlockType::AIR)
+    }
+}
+
+impl WorldGenerator for FlatGenerator {
+    fn generate(&self, chunk_pos: ChunkPos) -> Chunk {
+        let bottom = chunk_pos.origin().y;
+        let column: Vec<BlockType> = (0..CHUNK_SIZE as i32).map(|y| self.block_at(bottom + y)).collect();
+        if column.iter().all(|&block| block == column[0]) {
+            return Chunk::filled(column[0]);
+        }
+        let blocks: Vec<BlockType> = (0..CHUNK_VOLUME)
+            .map(|i| column[LocalPos::from_index(i).y])
+            .collect();
+        Chunk::from_blocks(&blocks)
+    }
+}
+
+// Nothing but air
+pub struct VoidGenerator;
+
+impl WorldGenerator for VoidGenerator {
+    fn generate(&self, _chunk_pos: ChunkPos) -> Chunk {
+        Chunk::filled(BlockType::AIR)
+    }
+}
+
+// A floor of alternating size by size squares at height, for checking meshing and alignment
+pub struct CheckerboardGenerator {
+    blocks: (BlockType, BlockType),
+    size: i32,
+    height: i32,
+}
+
+impl CheckerboardGenerator {
+    pub fn new(blocks: (BlockType, BlockType), size: u32, height: i32) -> Self {
+        Self {
+            blocks,
+            size: size as i32,
+            height,
+        }
+    }
+}
+
+impl WorldGenerator for CheckerboardGenerator {
+    fn generate(&self, chunk_pos: ChunkPos) -> Chunk {
+        if chunk_pos.y != self.height.div_euclid(CHUNK_SIZE as i32) {
+            return Chunk::filled(BlockType::AIR);
+        }
+        let mut blocks = [BlockType::AIR; CHUNK_VOLUME];
+        for (i, block) in blocks.iter_mut().enumerate() {
+            let pos = chunk_pos.world_pos(LocalPos::from_index(i));
+            if pos.y == self.height {
+                let square = pos.x.div_euclid(self.size) + pos.z.div_euclid(self.size);
+                *block = if square.rem_euclid(2) == 0 { self.blocks.0 } else { self.blocks.1 };
+            }
+        }
+        Chunk::from_blocks(&blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::vector;
+    use std::collections::{HashMap, HashSet};
+
+    const SEED: u64 = 0x5eed;
+
+    // positions around spawn, below, at and above sea level, on both sides of the origin
+    fn sample_positions() -> Vec<ChunkPos> {
+        let mut positions = Vec::new();
+        for x in -2..2 {
+            for y in -1..3 {
+                for z in -2..2 {
+                    positions.push(ChunkPos::new(x, y, z));
+                }
+            }
+        }
+        positions
+    }
+
+    // FNV-1a, so the expected hash doesn't depend on std's hasher
+    fn hash_chunks(chunks: &[Chunk]) -> u64 {
+        let mut hash = 0xcbf29ce484222325u64;
+        for chunk in chunks {
+            for byte in chunk.to_bytes() {
+                hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+
+    #[test]
+    fn generation_matches_recorded_hash() {
+        // update when world generation is meant to change, the same seed must keep making the
+        // same world otherwise
+        let generator = NoiseGenerator::new(SEED, Underground::builtin().unwrap());
+        let chunks: Vec<_> = sample_positions().into_iter().map(|pos| generator.generate(pos)).collect();
+        assert_eq!(hash_chunks(&chunks), 0xf1d741484296eef3);
+    }
+
+    #[test]
+    fn generation_is_independent_of_threads() {
+        use rayon::prelude::*;
+        let generator = NoiseGenerator::new(SEED, Underground::builtin().unwrap());
+        let positions = sample_positions();
+        let sequential: Vec<_> = positions.iter().map(|&pos| generator.generate(pos)).collect();
+        // generated in reverse on many threads, so no order is shared with the sequential run
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(8).build().unwrap();
+        let mut parallel: Vec<_> = pool.install(|| {
+            positions.par_iter().rev().map(|&pos| generator.generate(pos)).collect()
+        });
+        parallel.reverse();
+        assert_eq!(hash_chunks(&sequential), hash_chunks(&parallel));
+    }
+
+    #[test]
+    fn seeds_make_different_worlds() {
+        let pos = ChunkPos::new(0, 1, 0);
+        assert_ne!(NoiseGenerator::new(1, Underground::builtin().unwrap()).generate(pos).to_bytes(), NoiseGenerator::new(2, Underground::builtin().unwrap()).generate(pos).to_bytes());
+    }
+
+    #[test]
+    fn flat_layers_stack_from_zero() {
+        let preset = Preset::from_ron(r#"Flat([("stone", 32), ("dirt", 3), ("grass", 1)])"#).unwrap();
+        let generator = preset.build(SEED, Underground::builtin().unwrap()).unwrap();
+        let below = generator.generate(ChunkPos::new(0, -1, 0));
+        assert_eq!(below.uniform_block(), Some(BlockType::AIR));
+        let ground = generator.generate(ChunkPos::new(3, 0, -7));
+        assert_eq!(ground.uniform_block(), Some(BlockType::STONE));
+        let surface = generator.generate(ChunkPos::new(0, 1, 0));
+        assert_eq!(surface.get_block(LocalPos::new(5, 2, 9)), BlockType::DIRT);
+        assert_eq!(surface.get_block(LocalPos::new(5, 3, 9)), BlockType::GRASS);
+        assert_eq!(surface.get_block(LocalPos::new(5, 4, 9)), BlockType::AIR);
+    }
+
+    #[test]
+    fn checkerboard_alternates_across_chunks() {
+        let generator = CheckerboardGenerator::new((BlockType::STONE, BlockType::SAND), 4, -1);
+        let chunk = generator.generate(ChunkPos::new(-1, -1, 0));
+        let top = CHUNK_SIZE - 1;
+        // world x -4..0 and 0..4 are different squares, z 0..4 is the same one
+        assert_eq!(chunk.get_block(LocalPos::new(CHUNK_SIZE - 1, top, 0)), BlockType::SAND);
+        assert_eq!(generator.generate(ChunkPos::new(0, -1, 0)).get_block(LocalPos::new(0, top, 0)), BlockType::STONE);
+        assert_eq!(chunk.get_block(LocalPos::new(0, top - 1, 0)), BlockType::AIR);
+        assert!(Preset::from_ron(r#"Checkerboard(blocks: ("stone", "nothing"), size: 4, height: 0)"#).unwrap().build(SEED, Underground::builtin().unwrap()).is_err());
+    }
+
+    #[test]
+    fn biome_borders_blend() {
+        let generator = NoiseGenerator::new(SEED, Underground::builtin().unwrap());
+        let mut biomes = HashSet::new();
+        let mut previous: Option<i32> = None;
+        for cx in -200..200 {
+            let columns = generator.columns(cx * CHUNK_SIZE as i32, 0, CHUNK_SIZE);
+            biomes.extend(columns.iter().map(|column| column.biome));
+            // without blending, mountains start with a cliff more than 10 blocks high
+            for (x, column) in columns[..CHUNK_SIZE].iter().enumerate() {
+                let height = column.height;
+                if let Some(previous) = previous {
+                    assert!((height - previous).abs() <= 6, "step at x = {}", cx * CHUNK_SIZE as i32 + x as i32);
+                }
+                previous = Some(height);
+            }
+        }
+        assert_eq!(biomes.len(), BIOMES.len());
+    }
+
+    #[test]
+    fn caves_stay_dry() {
+        // chunks around sea level, below an ocean and its coast
+        let generator = NoiseGenerator::new(SEED, Underground::builtin().unwrap());
+        let mut world = HashMap::new();
+        for x in -3..3 {
+            for y in 0..2 {
+                for z in -3..3 {
+                    let pos = ChunkPos::new(x, y, z);
+                    world.insert(pos, generator.generate(pos));
+                }
+            }
+        }
+        let block = |pos: WorldPos| world.get(&pos.chunk()).map(|chunk| chunk.get_block(pos.local()));
+
+        let mut caves = 0;
+        for (&chunk_pos, chunk) in &world {
+            for i in 0..CHUNK_VOLUME {
+                let pos = chunk_pos.world_pos(LocalPos::from_index(i));
+                if pos.y > SEA_LEVEL || chunk.get_block(pos.local()) != BlockType::AIR {
+                    continue;
+                }
+                caves += 1;
+                // water flows down and sideways, so air above it is fine
+                for offset in [vector![1, 0, 0], vector![-1, 0, 0], vector![0, 0, 1], vector![0, 0, -1], vector![0, 1, 0]] {
+                    assert_ne!(block(pos + offset), Some(BlockType::WATER), "cave at {:?} floods", pos);
+                }
+            }
+        }
+        assert!(caves > 0);
+    }
+
+    #[test]
+    fn trees_cross_chunk_borders() {
+        // chunks at the surface around spawn, generated one by one
+        let generator = NoiseGenerator::new(SEED, Underground::builtin().unwrap());
+        let mut world = HashMap::new();
+        for x in -3..3 {
+            for y in 1..3 {
+                for z in -3..3 {
+                    let pos = ChunkPos::new(x, y, z);
+                    world.insert(pos, generator.generate(pos));
+                }
+            }
+        }
+        let block = |pos: WorldPos| world.get(&pos.chunk()).map(|chunk| chunk.get_block(pos.local()));
+
+        let mut crossing = 0;
+        for (&chunk_pos, chunk) in &world {
+            for i in 0..CHUNK_VOLUME {
+                let pos = chunk_pos.world_pos(LocalPos::from_index(i));
+                let anchor = pos + vector![0, -1, 0];
+                // the bottom of a trunk, other trees' leaves may cut through trunks higher up
+                if chunk.get_block(pos.local()) != BlockType::WOOD
+                || matches!(block(anchor), Some(BlockType::WOOD) | Some(BlockType::LEAVES)) {
+                    continue;
+                }
+                if let Some(ground) = block(anchor) {
+                    assert!(ground == BlockType::GRASS || Some(ground) == generator.snow, "tree at {:?} grows on {:?}", anchor, ground);
+                }
+                // the parts of the tree in the other chunks are there too, made by those chunks
+                for &(offset, _) in &generator.tree.blocks {
+                    let part = anchor + offset;
+                    if part.chunk() == chunk_pos {
+                        continue;
+                    }
+                    if let Some(part_block) = block(part) {
+                        assert_ne!(part_block, BlockType::AIR, "tree at {:?} is cut off at {:?}", anchor, part);
+                        crossing += 1;
+                    }
+                }
+            }
+        }
+        assert!(crossing > 0);
+    }
+
+    #[test]
+    fn plants_cross_chunk_borders() {
+        let generator = NoiseGenerator::new(SEED, Underground::builtin().unwrap());
+        let plants: HashSet<BlockType> = generator.biomes.iter()
+            .flat_map(|biome| biome.plants.iter().map(|&(plant, _, _)| plant))
+            .collect();
+        let mut world = HashMap::new();
+        for x in -3..3 {
+            for y in 1..4 {
+                for z in -3..3 {
+                    let pos = ChunkPos::new(x, y, z);
+                    world.insert(pos, generator.generate(pos));
+                }
+            }
+        }
+        let block = |pos: WorldPos| world.get(&pos.chunk()).map(|chunk| chunk.get_block(pos.local()));
+
+        let mut crossing = 0;
+        for (&chunk_pos, chunk) in &world {
+            for i in 0..CHUNK_VOLUME {
+                let pos = chunk_pos.world_pos(LocalPos::from_index(i));
+                let plant = chunk.get_block(pos.local());
+                if !plants.contains(&plant) || block(pos + vector![0, -1, 0]) == Some(plant) {
+                    continue;
+                }
+                // the bottom of a plant grows on the ground, however the chunks split it
+                let Some(ground) = block(pos + vector![0, -1, 0]) else { continue };
+                assert!(ground != BlockType::AIR && !plants.contains(&ground), "{:?} at {:?} grows on {:?}", plant, pos, ground);
+                let height = (0..).take_while(|&dy| block(pos + vector![0, dy, 0]) == Some(plant)).count();
+                assert!(height as i32 <= MAX_PLANT_HEIGHT);
+                if (pos + vector![0, height as i32 - 1, 0]).chunk() != (pos + vector![0, -1, 0]).chunk() {
+                    crossing += 1;
+                }
+            }
+        }
+        assert!(crossing > 0);
+    }
+}